@@ -0,0 +1,224 @@
+use std::num::NonZeroU32;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::Instant;
+
+type StdDuration = std::time::Duration;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+
+pub type SharedTokenBucket = Arc<Mutex<TokenBucket>>;
+
+/// Caps the bytes we send, across all clients. Refills at `rate` bytes per second, up to `capacity`.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: u64,
+    capacity: u64,
+    tokens: u64,
+    /// Sent beyond what we had, paid back before the bucket fills up again.
+    debt: u64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Starts out full.
+    pub fn new(rate: NonZeroU32, capacity: NonZeroU32, now: Instant) -> Self {
+        Self {
+            rate: rate.get().into(),
+            capacity: capacity.get().into(),
+            tokens: capacity.get().into(),
+            debt: 0,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_nanos();
+
+        let added = elapsed * u128::from(self.rate) / NANOS_PER_SECOND;
+
+        if added == 0 {
+            // keep accumulating the elapsed time
+            return;
+        }
+
+        let repaid = added.min(u128::from(self.debt));
+
+        self.debt -= u64::try_from(repaid).expect("At most the debt");
+
+        let tokens = u128::from(self.tokens) + added - repaid;
+
+        if tokens >= u128::from(self.capacity) {
+            self.tokens = self.capacity;
+            self.last_refill = now;
+        } else {
+            self.tokens = u64::try_from(tokens).expect("Less than capacity");
+            // only advance by the time the added tokens took, so we don't lose the remainder
+            self.last_refill += nanos_for(added, self.rate);
+        }
+    }
+
+    /// Takes `bytes`, or returns how long until they're available. More than the capacity never
+    /// is, so that takes a full bucket, and returns what it took. Settle the rest after sending.
+    pub fn take(&mut self, bytes: usize, now: Instant) -> Result<usize, StdDuration> {
+        self.refill(now);
+
+        let bytes = u64::try_from(bytes).unwrap_or(u64::MAX).min(self.capacity);
+
+        if let Some(remaining) = self.tokens.checked_sub(bytes) {
+            self.tokens = remaining;
+
+            Ok(usize::try_from(bytes).expect("At most what was asked for"))
+        } else {
+            // the debt gets paid first
+            let missing = bytes + self.debt - self.tokens;
+
+            Err(nanos_for(missing.into(), self.rate)
+                .saturating_sub(now.saturating_duration_since(self.last_refill)))
+        }
+    }
+
+    /// Settles taking `reserved` bytes, of which we ended up sending `sent`.
+    pub fn settle(&mut self, reserved: usize, sent: usize) {
+        if let Some(unused) = reserved.checked_sub(sent) {
+            self.refund(unused);
+        } else {
+            self.consume(sent - reserved);
+        }
+    }
+
+    /// Returns what was taken but not sent.
+    pub fn refund(&mut self, bytes: usize) {
+        self.tokens = self
+            .tokens
+            .saturating_add(u64::try_from(bytes).unwrap_or(u64::MAX))
+            .min(self.capacity);
+    }
+
+    /// Takes `bytes` that were sent without asking, going into debt if we don't have them.
+    pub fn consume(&mut self, bytes: usize) {
+        let bytes = u64::try_from(bytes).unwrap_or(u64::MAX);
+
+        if let Some(remaining) = self.tokens.checked_sub(bytes) {
+            self.tokens = remaining;
+        } else {
+            self.debt = self.debt.saturating_add(bytes - self.tokens);
+            self.tokens = 0;
+        }
+    }
+
+    /// Percentage of the bucket that's in use.
+    pub fn utilisation(&mut self, now: Instant) -> u64 {
+        self.refill(now);
+
+        (self.capacity - self.tokens) * 100 / self.capacity
+    }
+}
+
+/// How long it takes to refill `tokens` at `rate` bytes per second, rounded up.
+fn nanos_for(tokens: u128, rate: u64) -> StdDuration {
+    let nanos = (tokens * NANOS_PER_SECOND).div_ceil(u128::from(rate));
+
+    StdDuration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tokio::time::Instant;
+
+    use crate::bandwidth::TokenBucket;
+
+    #[test]
+    fn defers_when_exhausted() {
+        let start = Instant::now();
+
+        let mut bucket = TokenBucket::new(
+            NonZeroU32::new(100).unwrap(),
+            NonZeroU32::new(200).unwrap(),
+            start,
+        );
+
+        assert_eq!(bucket.take(150, start), Ok(150));
+        assert_eq!(bucket.utilisation(start), 75);
+
+        // 50 left, 30 more take 300ms
+        assert_eq!(bucket.take(80, start), Err(Duration::from_millis(300)));
+
+        let later = start + Duration::from_millis(100);
+
+        assert_eq!(bucket.take(80, later), Err(Duration::from_millis(200)));
+
+        let even_later = start + Duration::from_millis(300);
+
+        assert_eq!(bucket.take(80, even_later), Ok(80));
+        assert_eq!(bucket.utilisation(even_later), 100);
+    }
+
+    #[test]
+    fn refunds_and_refills_up_to_capacity() {
+        let start = Instant::now();
+
+        let mut bucket = TokenBucket::new(
+            NonZeroU32::new(10).unwrap(),
+            NonZeroU32::new(100).unwrap(),
+            start,
+        );
+
+        assert_eq!(bucket.take(50, start), Ok(50));
+
+        bucket.settle(50, 10);
+
+        assert_eq!(bucket.utilisation(start), 10);
+
+        bucket.consume(50);
+
+        assert_eq!(bucket.utilisation(start), 60);
+
+        bucket.settle(10, 20);
+
+        assert_eq!(bucket.utilisation(start), 70);
+
+        bucket.refund(10);
+
+        // 15 tokens
+        let later = start + Duration::from_millis(1550);
+
+        assert_eq!(bucket.utilisation(later), 45);
+        assert_eq!(bucket.take(56, later), Err(Duration::from_millis(50)));
+
+        assert_eq!(bucket.utilisation(start + Duration::from_hours(1)), 0);
+    }
+
+    #[test]
+    fn charges_lines_longer_than_the_burst() {
+        let start = Instant::now();
+
+        let mut bucket = TokenBucket::new(
+            NonZeroU32::new(100).unwrap(),
+            NonZeroU32::new(100).unwrap(),
+            start,
+        );
+
+        assert_eq!(bucket.take(1000, start), Ok(100), "Capped at capacity");
+
+        // what was reserved, not what was asked for, gets settled
+        bucket.settle(100, 30);
+
+        assert_eq!(bucket.utilisation(start), 30);
+
+        assert_eq!(bucket.take(70, start), Ok(70));
+
+        bucket.settle(70, 1000);
+
+        // 930 in debt, then 1 more
+        assert_eq!(bucket.take(1, start), Err(Duration::from_millis(9310)));
+
+        let later = start + Duration::from_millis(9310);
+
+        assert_eq!(bucket.take(1, later), Ok(1));
+    }
+}
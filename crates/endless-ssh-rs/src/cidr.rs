@@ -0,0 +1,155 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use color_eyre::eyre;
+use serde::Deserialize;
+
+/// An address range like `192.0.2.0/24` or `2001:db8::/32`. A bare address is a range of one.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_length: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = mask_v4(self.prefix_length);
+
+                u32::from(network) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = mask_v6(self.prefix_length);
+
+                u128::from(network) & mask == u128::from(ip) & mask
+            },
+            (IpAddr::V4(_), IpAddr::V6(_)) | (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+fn mask_v4(prefix_length: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_length))
+        .unwrap_or(0)
+}
+
+fn mask_v6(prefix_length: u8) -> u128 {
+    u128::MAX
+        .checked_shl(128 - u32::from(prefix_length))
+        .unwrap_or(0)
+}
+
+impl FromStr for Cidr {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_length) = match value.split_once('/') {
+            Some((address, prefix_length)) => (address, Some(prefix_length)),
+            None => (value, None),
+        };
+
+        let address: IpAddr = address
+            .parse::<IpAddr>()
+            .map_err(|error| {
+                eyre::Report::new(error).wrap_err(format!("Invalid CIDR {:?}", value))
+            })?
+            .to_canonical();
+
+        let max_prefix_length = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        let prefix_length = match prefix_length {
+            Some(prefix_length) => prefix_length
+                .parse::<u8>()
+                .ok()
+                .filter(|&prefix_length| prefix_length <= max_prefix_length)
+                .ok_or_else(|| {
+                    eyre::Report::msg(format!("Invalid prefix length in {:?}", value))
+                })?,
+            None => max_prefix_length,
+        };
+
+        // zero the host bits, so that `Display` shows the actual network
+        let network = match address {
+            IpAddr::V4(address) => IpAddr::V4((u32::from(address) & mask_v4(prefix_length)).into()),
+            IpAddr::V6(address) => {
+                IpAddr::V6((u128::from(address) & mask_v6(prefix_length)).into())
+            },
+        };
+
+        Ok(Self {
+            network,
+            prefix_length,
+        })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = eyre::Report;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::cidr::Cidr;
+
+    #[test]
+    fn ipv4_range() {
+        let cidr: Cidr = "192.0.2.77/24".parse().unwrap();
+
+        assert_eq!(cidr.to_string(), "192.0.2.0/24");
+        assert!(cidr.contains("192.0.2.1".parse().unwrap()), "In range");
+        assert!(
+            cidr.contains("::ffff:192.0.2.1".parse().unwrap()),
+            "Mapped, in range"
+        );
+        assert!(!cidr.contains("192.0.3.1".parse().unwrap()), "Out of range");
+        assert!(
+            !cidr.contains("2001:db8::1".parse().unwrap()),
+            "Other family"
+        );
+    }
+
+    #[test]
+    fn ipv6_range() {
+        let cidr: Cidr = "2001:db8::/32".parse().unwrap();
+
+        assert!(cidr.contains("2001:db8:1::1".parse().unwrap()), "In range");
+        assert!(
+            !cidr.contains("2001:db9::1".parse().unwrap()),
+            "Out of range"
+        );
+    }
+
+    #[test]
+    fn bare_address_and_everything() {
+        let single: Cidr = "192.0.2.1".parse().unwrap();
+        let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+
+        assert_eq!(single.to_string(), "192.0.2.1/32");
+        assert!(!single.contains("192.0.2.2".parse().unwrap()), "Single");
+        assert!(everything.contains("198.51.100.1".parse().unwrap()), "Any");
+    }
+
+    #[test]
+    fn invalid_prefix_length() {
+        #[expect(unused_must_use, reason = "Testing")]
+        "192.0.2.0/33".parse::<Cidr>().unwrap_err();
+    }
+}
@@ -1,17 +1,27 @@
 use std::env;
 use std::ffi::OsString;
-use std::num::{NonZeroU8, NonZeroU16};
+use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroUsize};
+use std::path::PathBuf;
+use std::str::FromStr as _;
+use std::sync::Arc;
 use std::time::Duration;
 
+use clap::builder::RangedU64ValueParser;
 use clap::error::ErrorKind;
 use clap::{ArgAction, Parser, value_parser};
 use color_eyre::eyre;
 use tracing::{Level, event};
 
 use crate::config::{
-    BindFamily, Config, DEFAULT_DELAY_MS, DEFAULT_MAX_CLIENTS, DEFAULT_MAX_LINE_LENGTH,
-    DEFAULT_PORT,
+    BindFamily, Config, ConfigFile, DEFAULT_DELAY_MS, DEFAULT_HISTORY_SIZE, DEFAULT_MAX_CLIENTS,
+    DEFAULT_MAX_LINE_LENGTH, DEFAULT_OFFENDERS_COMMAND_TIMEOUT_SECONDS,
+    DEFAULT_OFFENDERS_INTERVAL_SECONDS, DEFAULT_OFFENDERS_NFT_TABLE, DEFAULT_OFFENDERS_SET,
+    DEFAULT_OFFENDERS_THRESHOLD_MINUTES, DEFAULT_OFFENDERS_TIMEOUT_SECONDS, DEFAULT_PORT,
+    DEFAULT_REPEAT_OFFENDER_VISITS, MAX_LINE_LENGTH_LIMIT, OffendersConfig, OffendersFormat,
 };
+use crate::line::shape::{Alphabet, LineLength};
+use crate::line::{Corpus, LineGenerator};
+use crate::protocol::ssh;
 
 fn delay_parser(value: &str) -> Result<Duration, clap::Error> {
     let timeout_ms = value
@@ -21,6 +31,25 @@ fn delay_parser(value: &str) -> Result<Duration, clap::Error> {
     Ok(Duration::from_millis(timeout_ms))
 }
 
+fn seconds_parser(value: &str) -> Result<Duration, clap::Error> {
+    let seconds = value
+        .parse()
+        .map_err(|_| clap::Error::new(ErrorKind::ValueValidation))?;
+
+    Ok(Duration::from_secs(seconds))
+}
+
+fn minutes_parser(value: &str) -> Result<Duration, clap::Error> {
+    let minutes: u64 = value
+        .parse()
+        .map_err(|_| clap::Error::new(ErrorKind::ValueValidation))?;
+
+    minutes
+        .checked_mul(60)
+        .map(Duration::from_secs)
+        .ok_or_else(|| clap::Error::new(ErrorKind::ValueValidation))
+}
+
 #[derive(Debug, Parser)]
 #[command(disable_help_flag = true)]
 pub struct Cli {
@@ -42,6 +71,13 @@ pub struct Cli {
     )]
     only_6: bool,
 
+    #[clap(
+        short = 'c',
+        long = "config",
+        help = "Config file (TOML) with the rules"
+    )]
+    config: Option<PathBuf>,
+
     #[clap(
         short = 'd',
         long = "delay",
@@ -55,10 +91,26 @@ pub struct Cli {
         short = 'l',
         long = "max-line-length",
         default_value_t = DEFAULT_MAX_LINE_LENGTH.get(),
-        help = "Maximum banner line length (3-255)",
-        value_parser = value_parser!(u8).range(3..=255)
+        help = format!("Maximum banner line length (3-{MAX_LINE_LENGTH_LIMIT}, up to {} for SSH)", ssh::MAX_LINE_LENGTH),
+        value_parser = value_parser!(u16).range(3..=i64::from(MAX_LINE_LENGTH_LIMIT))
+    )]
+    max_line_length: u16,
+
+    #[clap(
+        long = "line-length",
+        default_value_t = LineLength::default(),
+        help = "Length distribution of the random lines: uniform, fixed, normal:<mean>:<standard deviation> or zipf:<exponent>",
+        value_parser = LineLength::from_str
     )]
-    max_line_length: u8,
+    line_length: LineLength,
+
+    #[clap(
+        long = "alphabet",
+        default_value_t = Alphabet::default(),
+        help = "Characters of the random lines: printable, alphanumeric, hex, base64 or custom:<characters>",
+        value_parser = Alphabet::from_str
+    )]
+    alphabet: Alphabet,
 
     #[clap(
         short = 'm',
@@ -69,6 +121,88 @@ pub struct Cli {
     )]
     max_clients: u8,
 
+    #[clap(
+        long = "max-clients-per-ip",
+        help = "Maximum number of clients per source address, repeat offenders are exempt",
+        value_parser = value_parser!(u8).range(1..)
+    )]
+    max_clients_per_ip: Option<u8>,
+
+    #[clap(
+        long = "max-bandwidth",
+        help = "Maximum bytes per second sent to all clients combined, delays are stretched to stay under it",
+        value_parser = value_parser!(u32).range(1..)
+    )]
+    max_bandwidth: Option<u32>,
+
+    #[clap(
+        long = "bandwidth-burst",
+        help = "Bytes that can be sent at once before the bandwidth cap kicks in [default: max-bandwidth]",
+        value_parser = value_parser!(u32).range(1..),
+        requires = "max_bandwidth"
+    )]
+    bandwidth_burst: Option<u32>,
+
+    #[clap(
+        long = "reserved-slots",
+        default_value_t = 0,
+        help = "Number of client slots only repeat offenders can take"
+    )]
+    reserved_slots: u8,
+
+    #[clap(
+        long = "history-file",
+        help = "File to load the repeat offender history from and persist it to"
+    )]
+    history_file: Option<PathBuf>,
+
+    #[clap(
+        long = "corpus-file",
+        help = "Text file to draw the lines we send from, instead of random characters"
+    )]
+    corpus_file: Option<PathBuf>,
+
+    #[clap(
+        long = "line-generator",
+        help = "Where the lines we send come from [default: corpus with --corpus-file, random without]"
+    )]
+    line_generator: Option<LineGenerator>,
+
+    #[clap(
+        long = "disguise",
+        help = "Vary delays, line shapes and banners per connection, so scanners can't fingerprint the tarpit"
+    )]
+    disguise: bool,
+
+    #[clap(
+        long = "seed",
+        help = "Seed of the random streams, so each session can be replayed from it and its session id"
+    )]
+    seed: Option<u64>,
+
+    #[clap(
+        long = "history-size",
+        default_value_t = DEFAULT_HISTORY_SIZE.get(),
+        help = "Maximum number of source addresses to remember",
+        value_parser = RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    history_size: usize,
+
+    #[clap(
+        long = "repeat-offender-visits",
+        default_value_t = DEFAULT_REPEAT_OFFENDER_VISITS.get(),
+        help = "Number of visits after which a source is a repeat offender",
+        value_parser = value_parser!(u32).range(1..)
+    )]
+    repeat_offender_visits: u32,
+
+    #[clap(
+        long = "repeat-offender-delay",
+        help = "Message millisecond delay for repeat offenders",
+        value_parser = delay_parser
+    )]
+    repeat_offender_delay: Option<Duration>,
+
     #[clap(
         short = 'p',
         long = "port",
@@ -78,6 +212,79 @@ pub struct Cli {
     )]
     port: u16,
 
+    #[clap(
+        long = "offenders-file",
+        help = "Periodically write sources that spent too long in the tarpit to this file"
+    )]
+    offenders_file: Option<PathBuf>,
+
+    #[clap(
+        long = "offenders-format",
+        default_value_t = OffendersFormat::Nft,
+        help = "Format of the offenders file",
+        requires = "offenders_file"
+    )]
+    offenders_format: OffendersFormat,
+
+    #[clap(
+        long = "offenders-threshold",
+        default_value = DEFAULT_OFFENDERS_THRESHOLD_MINUTES.to_string(),
+        help = "Minutes a source needs to spend in the tarpit before it becomes an offender",
+        value_parser = minutes_parser,
+        requires = "offenders_file"
+    )]
+    offenders_threshold: Duration,
+
+    #[clap(
+        long = "offenders-timeout",
+        default_value = DEFAULT_OFFENDERS_TIMEOUT_SECONDS.to_string(),
+        help = "Seconds an offender stays in the set after it was last seen",
+        value_parser = seconds_parser,
+        requires = "offenders_file"
+    )]
+    offenders_timeout: Duration,
+
+    #[clap(
+        long = "offenders-interval",
+        default_value = DEFAULT_OFFENDERS_INTERVAL_SECONDS.to_string(),
+        help = "Seconds between writes of the offenders file",
+        value_parser = seconds_parser,
+        requires = "offenders_file"
+    )]
+    offenders_interval: Duration,
+
+    #[clap(
+        long = "offenders-set",
+        default_value = DEFAULT_OFFENDERS_SET,
+        help = "Name of the offenders set, suffixed with _v4 and _v6",
+        requires = "offenders_file"
+    )]
+    offenders_set: String,
+
+    #[clap(
+        long = "offenders-nft-table",
+        default_value = DEFAULT_OFFENDERS_NFT_TABLE,
+        help = "nftables table (family inet) that holds the offenders sets",
+        requires = "offenders_file"
+    )]
+    offenders_nft_table: String,
+
+    #[clap(
+        long = "offenders-command",
+        help = "Command run with `sh -c` after each write of the offenders file",
+        requires = "offenders_file"
+    )]
+    offenders_command: Option<String>,
+
+    #[clap(
+        long = "offenders-command-timeout",
+        default_value = DEFAULT_OFFENDERS_COMMAND_TIMEOUT_SECONDS.to_string(),
+        help = "Seconds the offenders command may run before it is killed",
+        value_parser = seconds_parser,
+        requires = "offenders_file"
+    )]
+    offenders_command_timeout: Duration,
+
     #[clap(
         short = 'h',
         long = "help",
@@ -100,12 +307,58 @@ impl From<Cli> for Config {
             (true, true) => unreachable!("Guaranteed by clap"),
         };
 
+        let offenders = matches.offenders_file.map(|path| OffendersConfig {
+            path,
+            format: matches.offenders_format,
+            threshold: matches.offenders_threshold,
+            entry_timeout: matches.offenders_timeout,
+            interval: matches.offenders_interval,
+            set_name: matches.offenders_set,
+            nft_table: matches.offenders_nft_table,
+            command: matches.offenders_command,
+            command_timeout: matches.offenders_command_timeout,
+        });
+
+        // given a corpus, that's what the lines come from
+        let line_generator = matches
+            .line_generator
+            .unwrap_or(if matches.corpus_file.is_some() {
+                LineGenerator::Corpus
+            } else {
+                LineGenerator::default()
+            });
+
         Config {
+            alphabet: matches.alphabet,
+            bandwidth_burst: matches
+                .bandwidth_burst
+                .map(|burst| NonZeroU32::new(burst).expect("Guaranteed by clap")),
             bind_family,
+            corpus: None,
             delay: matches.delay,
+            disguise: matches.disguise,
+            history_file: matches.history_file,
+            history_size: NonZeroUsize::new(matches.history_size).expect("Guaranteed by clap"),
+            line_generator,
+            line_length: matches.line_length,
+            listeners: Vec::new(),
+            max_bandwidth: matches
+                .max_bandwidth
+                .map(|max| NonZeroU32::new(max).expect("Guaranteed by clap")),
             max_clients: NonZeroU8::new(matches.max_clients).expect("Guaranteed by clap"),
-            max_line_length: NonZeroU8::new(matches.max_line_length).expect("Guaranteed by clap"),
+            max_clients_per_ip: matches
+                .max_clients_per_ip
+                .map(|max| NonZeroU8::new(max).expect("Guaranteed by clap")),
+            max_line_length: NonZeroU16::new(matches.max_line_length).expect("Guaranteed by clap"),
+            offenders,
             port: NonZeroU16::new(matches.port).expect("Guaranteed by clap"),
+            repeat_offender_delay: matches.repeat_offender_delay,
+            repeat_offender_visits: NonZeroU32::new(matches.repeat_offender_visits)
+                .expect("Guaranteed by clap"),
+            reserved_slots: matches.reserved_slots,
+            rules: Vec::new(),
+            schedule: None,
+            seed: matches.seed,
         }
     }
 }
@@ -119,20 +372,111 @@ where
     I: IntoIterator<Item = T>,
     T: Into<OsString> + Clone,
 {
-    Ok(Cli::try_parse_from(from)?.into())
+    let cli = Cli::try_parse_from(from)?;
+
+    let config_file = cli.config.as_deref().map(ConfigFile::load).transpose()?;
+    let corpus = cli.corpus_file.as_deref().map(Corpus::load).transpose()?;
+
+    let mut config: Config = cli.into();
+
+    config.corpus = corpus.map(Arc::new);
+
+    if let Some(config_file) = config_file {
+        config.listeners = config_file.listeners;
+        config.rules = config_file.rules;
+        config.schedule = config_file.schedule;
+    }
+
+    if config.corpus.is_none() && config.line_generator.needs_corpus() {
+        return Err(eyre::Report::msg(format!(
+            "The {} line generator needs --corpus-file",
+            config.line_generator
+        )));
+    }
+
+    if config.corpus.is_none()
+        && let Some(rule) = config.rules.iter().find(|rule| {
+            rule.action
+                .line_generator
+                .is_some_and(LineGenerator::needs_corpus)
+        })
+    {
+        return Err(eyre::Report::msg(format!(
+            "Rule {:?}: the {} line generator needs --corpus-file",
+            rule.name,
+            rule.action.line_generator.unwrap_or_default()
+        )));
+    }
+
+    // any of the protocols can get any of the lengths
+    let line_lengths = config
+        .rules
+        .iter()
+        .filter_map(|rule| rule.action.max_line_length);
+    let (shortest, longest) = line_lengths.fold(
+        (config.max_line_length, config.max_line_length),
+        |(shortest, longest), length| (shortest.min(length), longest.max(length)),
+    );
+
+    let protocols = config
+        .listeners()
+        .iter()
+        .map(|listener| listener.protocol)
+        .chain(config.rules.iter().filter_map(|rule| rule.action.protocol))
+        .collect::<Vec<_>>();
+
+    if let Some(protocol) = protocols
+        .iter()
+        .find(|protocol| shortest.get() < protocol.min_line_length())
+    {
+        return Err(eyre::Report::msg(format!(
+            "A maximum line length of {} is too short for {}, it needs at least {}",
+            shortest,
+            protocol,
+            protocol.min_line_length()
+        )));
+    }
+
+    if let Some(protocol) = protocols
+        .iter()
+        .find(|protocol| longest.get() > protocol.max_line_length())
+    {
+        return Err(eyre::Report::msg(format!(
+            "A maximum line length of {} is too long for {}, its clients take at most {}",
+            longest,
+            protocol,
+            protocol.max_line_length()
+        )));
+    }
+
+    if config.reserved_slots >= config.max_clients.get() {
+        return Err(eyre::Report::msg(
+            "Reserved slots need to be less than the maximum number of clients",
+        ));
+    }
+
+    Ok(config)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::num::{NonZeroU8, NonZeroU16};
+    use std::ffi::OsString;
+    use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroUsize};
+    use std::path::PathBuf;
+    use std::time::Duration;
 
     use color_eyre::eyre;
     use pretty_assertions::assert_eq;
 
     use super::parse_cli_from;
-    use crate::config::{BindFamily, Config};
-
-    fn parse_factory(input: &'static str) -> Result<Config, eyre::Report> {
+    use crate::config::{
+        BindFamily, Config, ConfigFile, MAX_LINE_LENGTH_LIMIT, OffendersConfig, OffendersFormat,
+    };
+    use crate::line::LineGenerator;
+    use crate::line::shape::{Alphabet, LineLength};
+    use crate::protocol::Protocol;
+
+    fn parse_factory(input: &str) -> Result<Config, eyre::Report> {
         // fake input
         let command_line = input.split_whitespace().collect::<Vec<&str>>();
 
@@ -199,7 +543,7 @@ mod tests {
         let result = parse_factory("endless-ssh-rs --max-line-length 70");
 
         let expected_config = Config {
-            max_line_length: NonZeroU8::new(70).unwrap(),
+            max_line_length: NonZeroU16::new(70).unwrap(),
             ..Config::default()
         };
 
@@ -215,6 +559,66 @@ mod tests {
         result.unwrap_err();
     }
 
+    #[test]
+    fn ensures_maximum_line_length() {
+        let result = parse_factory("endless-ssh-rs --max-line-length 8192");
+
+        assert_eq!(result.unwrap().max_line_length.get(), 8192);
+
+        let result = parse_factory("endless-ssh-rs --max-line-length 8193");
+
+        #[expect(unused_must_use, reason = "Testing")]
+        result.unwrap_err();
+    }
+
+    #[test]
+    fn allows_longer_lines_without_ssh() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+
+        std::fs::write(&path, "[[listeners]]\nport = 80\nprotocol = \"http\"\n").unwrap();
+
+        let parse = |max_line_length: u16| {
+            parse_cli_from([
+                "endless-ssh-rs".into(),
+                "--config".into(),
+                path.clone().into_os_string(),
+                "--max-line-length".into(),
+                max_line_length.to_string().into(),
+            ])
+        };
+
+        assert_eq!(
+            parse(MAX_LINE_LENGTH_LIMIT).unwrap().max_line_length.get(),
+            MAX_LINE_LENGTH_LIMIT
+        );
+
+        #[expect(unused_must_use, reason = "Testing")]
+        parse(MAX_LINE_LENGTH_LIMIT + 1).unwrap_err();
+    }
+
+    #[test]
+    fn parses_line_shape() {
+        let result = parse_factory("endless-ssh-rs --line-length normal:20:5 --alphabet hex");
+
+        let expected_config = Config {
+            line_length: LineLength::Normal {
+                mean: 20.0,
+                std_dev: 5.0,
+            },
+            alphabet: Alphabet::Hex,
+            ..Config::default()
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), expected_config);
+
+        for arguments in ["--line-length normal:20", "--alphabet custom:"] {
+            #[expect(unused_must_use, reason = "Testing")]
+            parse_factory(&format!("endless-ssh-rs {}", arguments)).unwrap_err();
+        }
+    }
+
     #[test]
     fn parses_ipv4_only() {
         let result = parse_factory("endless-ssh-rs -4");
@@ -260,4 +664,301 @@ mod tests {
         #[expect(unused_must_use, reason = "Testing")]
         result.unwrap_err();
     }
+
+    #[test]
+    fn parses_offenders() {
+        let result = parse_factory(
+            "endless-ssh-rs --offenders-file /run/offenders.txt --offenders-format ipset --offenders-threshold 5 --offenders-timeout 3600 --offenders-command true --offenders-command-timeout 10",
+        );
+
+        let expected_config = Config {
+            offenders: Some(OffendersConfig {
+                path: PathBuf::from("/run/offenders.txt"),
+                format: OffendersFormat::Ipset,
+                threshold: Duration::from_mins(5),
+                entry_timeout: Duration::from_hours(1),
+                interval: Duration::from_mins(1),
+                set_name: "endless_ssh_offenders".to_owned(),
+                nft_table: "endless_ssh".to_owned(),
+                command: Some("true".to_owned()),
+                command_timeout: Duration::from_secs(10),
+            }),
+            ..Config::default()
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), expected_config);
+    }
+
+    #[test]
+    fn offenders_options_require_offenders_file() {
+        let result = parse_factory("endless-ssh-rs --offenders-format ipset");
+
+        #[expect(unused_must_use, reason = "Testing")]
+        result.unwrap_err();
+    }
+
+    #[test]
+    fn parses_repeat_offender_options() {
+        let result = parse_factory(
+            "endless-ssh-rs --history-file /var/lib/history --history-size 100 --repeat-offender-visits 3 --repeat-offender-delay 20000 --reserved-slots 4 --max-clients-per-ip 2",
+        );
+
+        let expected_config = Config {
+            history_file: Some(PathBuf::from("/var/lib/history")),
+            history_size: NonZeroUsize::new(100).unwrap(),
+            repeat_offender_visits: NonZeroU32::new(3).unwrap(),
+            repeat_offender_delay: Some(Duration::from_secs(20)),
+            reserved_slots: 4,
+            max_clients_per_ip: Some(NonZeroU8::new(2).unwrap()),
+            ..Config::default()
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), expected_config);
+    }
+
+    #[test]
+    fn parses_bandwidth_options() {
+        let result = parse_factory("endless-ssh-rs --max-bandwidth 1000 --bandwidth-burst 4000");
+
+        let expected_config = Config {
+            max_bandwidth: Some(NonZeroU32::new(1000).unwrap()),
+            bandwidth_burst: Some(NonZeroU32::new(4000).unwrap()),
+            ..Config::default()
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), expected_config);
+
+        #[expect(unused_must_use, reason = "Testing")]
+        parse_factory("endless-ssh-rs --bandwidth-burst 4000").unwrap_err();
+    }
+
+    #[test]
+    fn parses_seed() {
+        let result = parse_factory("endless-ssh-rs --seed 4253");
+
+        let expected_config = Config {
+            seed: Some(4253),
+            ..Config::default()
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), expected_config);
+
+        #[expect(unused_must_use, reason = "Testing")]
+        parse_factory("endless-ssh-rs --seed -1").unwrap_err();
+    }
+
+    #[test]
+    fn parses_disguise() {
+        let result = parse_factory("endless-ssh-rs --disguise");
+
+        let expected_config = Config {
+            disguise: true,
+            ..Config::default()
+        };
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), expected_config);
+
+        #[expect(unused_must_use, reason = "Testing")]
+        parse_factory("endless-ssh-rs --disguise yes").unwrap_err();
+    }
+
+    #[test]
+    fn reserved_slots_must_leave_room() {
+        let result = parse_factory("endless-ssh-rs --max-clients 4 --reserved-slots 4");
+
+        #[expect(unused_must_use, reason = "Testing")]
+        result.unwrap_err();
+    }
+
+    #[test]
+    fn reads_rules_from_config_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+
+        std::fs::write(
+            &path,
+            "[[rules]]\nname = \"everyone\"\naction = { priority = true }\n",
+        )
+        .unwrap();
+
+        let config = parse_cli_from([
+            "endless-ssh-rs".into(),
+            "--config".into(),
+            path.into_os_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config
+                .rules
+                .iter()
+                .map(|rule| rule.name.as_str())
+                .collect::<Vec<_>>(),
+            ["everyone"]
+        );
+    }
+
+    #[test]
+    fn reads_listeners_from_config_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+
+        std::fs::write(
+            &path,
+            "[[listeners]]\nport = 22\n\n[[listeners]]\nport = 2222\nprotocol = \"ssh\"\n",
+        )
+        .unwrap();
+
+        let config = parse_cli_from([
+            "endless-ssh-rs".into(),
+            "--port".into(),
+            "2000".into(),
+            "--config".into(),
+            path.into_os_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config
+                .listeners()
+                .iter()
+                .map(|listener| (listener.port.get(), listener.protocol))
+                .collect::<Vec<_>>(),
+            [(22, Protocol::Ssh), (2222, Protocol::Ssh)]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_listeners() {
+        let result = ConfigFile::parse("[[listeners]]\nport = 22\n\n[[listeners]]\nport = 22\n");
+
+        #[expect(unused_must_use, reason = "Testing")]
+        result.unwrap_err();
+    }
+
+    #[test]
+    fn rejects_broken_config_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+
+        std::fs::write(
+            &path,
+            "[[rules]]\nname = \"short\"\naction = { max_line_length = 2 }\n",
+        )
+        .unwrap();
+
+        let result = parse_cli_from([
+            "endless-ssh-rs".into(),
+            "--config".into(),
+            path.into_os_string(),
+        ]);
+
+        #[expect(unused_must_use, reason = "Testing")]
+        result.unwrap_err();
+    }
+
+    #[test]
+    fn rejects_line_lengths_too_short_for_the_protocol() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("config.toml");
+
+        std::fs::write(&path, "[[listeners]]\nport = 25\nprotocol = \"smtp\"\n").unwrap();
+
+        let parse = |max_line_length: &str| {
+            parse_cli_from([
+                "endless-ssh-rs".into(),
+                "--config".into(),
+                path.clone().into_os_string(),
+                "--max-line-length".into(),
+                max_line_length.into(),
+            ])
+        };
+
+        assert_eq!(parse("7").unwrap().max_line_length.get(), 7);
+
+        #[expect(unused_must_use, reason = "Testing")]
+        parse("6").unwrap_err();
+    }
+
+    #[test]
+    fn reads_corpus_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let corpus_path = directory.path().join("corpus.txt");
+        let config_path = directory.path().join("config.toml");
+
+        std::fs::write(&corpus_path, "first\nsecond\n").unwrap();
+        std::fs::write(
+            &config_path,
+            "[[rules]]\nname = \"corpus\"\naction = { line_generator = \"corpus\" }\n",
+        )
+        .unwrap();
+
+        let result = parse_cli_from([
+            "endless-ssh-rs".into(),
+            "--config".into(),
+            config_path.clone().into_os_string(),
+        ]);
+
+        #[expect(unused_must_use, reason = "Testing")]
+        result.unwrap_err();
+
+        let config = parse_cli_from([
+            "endless-ssh-rs".into(),
+            "--config".into(),
+            config_path.into_os_string(),
+            "--corpus-file".into(),
+            corpus_path.into_os_string(),
+        ])
+        .unwrap();
+
+        assert_eq!(config.corpus.map(|corpus| corpus.len()), Some(2));
+    }
+
+    #[test]
+    fn selects_line_generator() {
+        let directory = tempfile::tempdir().unwrap();
+        let corpus_path = directory.path().join("corpus.txt");
+
+        std::fs::write(&corpus_path, "first\nsecond\n").unwrap();
+
+        let parse = |args: &[&str]| {
+            let corpus = ["--corpus-file".into(), corpus_path.clone().into_os_string()];
+
+            parse_cli_from(
+                ["endless-ssh-rs".into()]
+                    .into_iter()
+                    .chain(args.iter().map(OsString::from))
+                    .chain(corpus),
+            )
+        };
+
+        assert_eq!(parse(&[]).unwrap().line_generator, LineGenerator::Corpus);
+        assert_eq!(
+            parse(&["--line-generator", "markov"])
+                .unwrap()
+                .line_generator,
+            LineGenerator::Markov
+        );
+        assert_eq!(
+            parse(&["--line-generator", "random"])
+                .unwrap()
+                .line_generator,
+            LineGenerator::Random
+        );
+        assert_eq!(
+            parse_cli_from(["endless-ssh-rs"]).unwrap().line_generator,
+            LineGenerator::Random
+        );
+
+        #[expect(unused_must_use, reason = "Testing")]
+        parse_cli_from(["endless-ssh-rs", "--line-generator", "markov"]).unwrap_err();
+
+        #[expect(unused_must_use, reason = "Testing")]
+        parse(&["--line-generator", "fortune"]).unwrap_err();
+    }
 }
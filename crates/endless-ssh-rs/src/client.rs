@@ -1,17 +1,41 @@
 use std::net::SocketAddr;
+use std::sync::PoisonError;
 
-use time::SignedDuration;
+use rand::rngs::StdRng;
+use time::{OffsetDateTime, SignedDuration};
 use tokio::sync::OwnedSemaphorePermit;
 use tokio::time::Instant;
 use tracing::{Level, event};
 
+use crate::disguise;
+use crate::history::SharedHistory;
+use crate::protocol::{Capture, TarpitProtocol};
+use crate::random::{in_session, next_session_id, session_rng};
+use crate::rules::Treatment;
+
+type StdDuration = std::time::Duration;
+
 pub struct Client<S> {
+    /// Replays the session, together with the seed.
+    session_id: u64,
     time_spent: SignedDuration,
     send_next: Instant,
+    /// The delay we're waiting before `send_next`.
+    delay: StdDuration,
+    /// When we release the client, regardless.
+    expires_at: Option<Instant>,
     bytes_sent: usize,
     addr: SocketAddr,
+    repeat_offender: bool,
+    treatment: Treatment,
+    session: Box<dyn TarpitProtocol>,
+    /// Everything random the session does comes from here.
+    rng: StdRng,
+    /// A tick that didn't fit in the bandwidth cap, sent next time.
+    deferred: Option<Vec<u8>>,
     tcp_stream: S,
     permit: OwnedSemaphorePermit,
+    history: SharedHistory,
 }
 
 impl<S> std::cmp::Eq for Client<S> {}
@@ -38,10 +62,16 @@ impl<S> std::cmp::PartialOrd for Client<S> {
 impl<S> std::fmt::Debug for Client<S> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Client")
+            .field("session_id", &self.session_id)
             .field("time_spent", &self.time_spent)
             .field("send_next", &self.send_next)
+            .field("delay", &self.delay)
+            .field("expires_at", &self.expires_at)
             .field("bytes_sent", &self.bytes_sent)
             .field("addr", &self.addr)
+            .field("repeat_offender", &self.repeat_offender)
+            .field("treatment", &self.treatment)
+            // .field("session", &self.session)
             // .field("tcp_stream", &self.tcp_stream)
             .finish_non_exhaustive()
     }
@@ -51,19 +81,41 @@ impl<S> Client<S> {
     pub fn new(
         stream: S,
         addr: SocketAddr,
-        start_sending_at: Instant,
+        repeat_offender: bool,
+        mut treatment: Treatment,
         permit: OwnedSemaphorePermit,
+        history: SharedHistory,
+        seed: Option<u64>,
     ) -> Self {
+        let session_id = next_session_id();
+        let mut rng = session_rng(seed, session_id);
+
+        let now = Instant::now();
+        let delay = in_session(&mut rng, || disguise::start(&mut treatment));
+
         Self {
+            session_id,
             time_spent: SignedDuration::ZERO,
-            send_next: start_sending_at,
+            send_next: now + delay,
+            delay,
+            expires_at: treatment.lifetime.map(|lifetime| now + lifetime),
             addr,
+            repeat_offender,
+            session: treatment.protocol.session(),
+            treatment,
+            rng,
+            deferred: None,
             bytes_sent: 0,
             tcp_stream: stream,
             permit,
+            history,
         }
     }
 
+    pub fn session_id(&self) -> u64 {
+        self.session_id
+    }
+
     #[expect(unused, reason = "Consistency with other props")]
     pub fn time_spent(&self) -> SignedDuration {
         self.time_spent
@@ -81,6 +133,22 @@ impl<S> Client<S> {
         &mut self.send_next
     }
 
+    pub fn delay(&self) -> StdDuration {
+        self.delay
+    }
+
+    pub fn delay_mut(&mut self) -> &mut StdDuration {
+        &mut self.delay
+    }
+
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    pub fn treatment(&self) -> &Treatment {
+        &self.treatment
+    }
+
     #[expect(unused, reason = "Consistency with other props")]
     pub fn bytes_sent(&self) -> usize {
         self.bytes_sent
@@ -97,6 +165,34 @@ impl<S> Client<S> {
     pub fn tcp_stream_mut(&mut self) -> &mut S {
         &mut self.tcp_stream
     }
+
+    pub fn setup(&mut self) -> Vec<u8> {
+        in_session(&mut self.rng, || self.session.setup())
+    }
+
+    /// What we send next, the deferred tick if there is one.
+    pub fn tick(&mut self) -> Vec<u8> {
+        self.deferred
+            .take()
+            .unwrap_or_else(|| in_session(&mut self.rng, || self.session.tick(&self.treatment)))
+    }
+
+    /// Holds on to `bytes` for the next tick.
+    pub fn defer(&mut self, bytes: Vec<u8>) {
+        self.deferred = Some(bytes);
+    }
+
+    pub fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        in_session(&mut self.rng, || self.session.receive(data))
+    }
+
+    pub fn take_captures(&mut self) -> Vec<Capture> {
+        self.session.take_captures()
+    }
+
+    pub fn next_delay(&mut self) -> StdDuration {
+        in_session(&mut self.rng, || self.treatment.delay.next_delay())
+    }
 }
 
 impl<S> Drop for Client<S> {
@@ -105,11 +201,24 @@ impl<S> Drop for Client<S> {
         event!(
             Level::INFO,
             addr = %self.addr,
+            session_id = self.session_id,
             time_spent = %self.time_spent,
             bytes_sent = self.bytes_sent,
+            repeat_offender = self.repeat_offender,
+            rule = self.treatment.rule.as_deref(),
+            protocol = %self.treatment.protocol,
             "Dropping client...",
         );
 
+        self.history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .disconnect(
+                self.addr.ip(),
+                self.time_spent.unsigned_abs(),
+                OffsetDateTime::now_utc(),
+            );
+
         // no need to shut down the stream, it happens when it is dropped
 
         // Technically this client's permit isn't available until AFTER this function has ended
@@ -1,33 +1,60 @@
-use std::num::NonZeroU8;
+use std::collections::BinaryHeap;
+use std::sync::PoisonError;
 
 use tokio::net::TcpStream;
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::watch;
 use tokio::time::{Instant, sleep_until};
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, event};
 
+use crate::bandwidth::SharedTokenBucket;
 use crate::client::Client;
+use crate::offenders::OffenderMessage;
+use crate::protocol::{self, Capture};
+use crate::schedule::{OnClose, wait_for_transition};
 use crate::sender;
 use crate::statistics::StatisticsMessage;
 
+/// What we read from a client per tick, at most.
+const RECEIVE_BUFFER_SIZE: usize = 1024;
+
 pub async fn process_clients(
     cancellation_token: CancellationToken,
-    delay: std::time::Duration,
-    max_line_length: NonZeroU8,
-    client_sender: UnboundedSender<Client<TcpStream>>,
     mut client_receiver: UnboundedReceiver<Client<TcpStream>>,
     statistics_sender: UnboundedSender<StatisticsMessage>,
+    offenders_sender: Option<UnboundedSender<OffenderMessage>>,
+    mut active_receiver: watch::Receiver<bool>,
+    on_close: OnClose,
+    bandwidth: Option<SharedTokenBucket>,
 ) {
     let _guard = cancellation_token.clone().drop_guard();
 
     event!(Level::INFO, "Processing clients");
 
+    // clients have their own delays, so we always pick the one that's due first
+    let mut clients = BinaryHeap::<Client<TcpStream>>::new();
+
     loop {
+        let deadline = clients.peek().map(Client::send_next);
+
         tokio::select! {
             biased;
             () = cancellation_token.cancelled() => {
                 break;
             },
+            () = sleep_until_deadline(deadline) => {
+                let client = clients.pop().expect("We have a deadline, so we have a client");
+
+                let Some(client) = process_client(client, &statistics_sender, offenders_sender.as_ref(), bandwidth.as_ref()).await else {
+                    event!(Level::INFO, "Client gone");
+
+                    // no client to re-schedule
+                    continue;
+                };
+
+                clients.push(client);
+            },
             received_client = client_receiver.recv() => {
                 let Some(client) = received_client else {
                     event!(Level::ERROR, "Client receiver gone");
@@ -35,58 +62,198 @@ pub async fn process_clients(
                     break;
                 };
 
-                let Some(client) = process_client(client, cancellation_token.clone(), delay, max_line_length, &statistics_sender).await else {
+                let Some(client) = set_up_client(client, &statistics_sender, bandwidth.as_ref()).await else {
                     event!(Level::INFO, "Client gone");
 
-                    // no client to re-schedule
                     continue;
                 };
 
+                event!(Level::TRACE, addr = ?client.addr(), until_ready = ?client.send_next().duration_since(Instant::now()), "Scheduled client");
 
-                let Ok(()) = client_sender.send(client) else {
-                    event!(Level::ERROR, "Client sender gone");
+                clients.push(client);
+            },
+            active = wait_for_transition(&mut active_receiver) => {
+                if !active && on_close == OnClose::Release {
+                    event!(Level::INFO, released = clients.len(), "Schedule closed, releasing trapped clients");
 
-                    break;
-                };
+                    // dropping them closes the connections and returns the permits
+                    clients.clear();
+                }
             },
         }
     }
 }
 
-async fn process_client<S>(
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sends the protocol's setup, if it has any.
+async fn set_up_client<S>(
     mut client: Client<S>,
-    cancellation_token: CancellationToken,
-    delay: std::time::Duration,
-    max_line_length: NonZeroU8,
     statistics_sender: &UnboundedSender<StatisticsMessage>,
+    bandwidth: Option<&SharedTokenBucket>,
 ) -> Option<Client<S>>
 where
     S: tokio::io::AsyncWriteExt + std::marker::Unpin + std::fmt::Debug,
 {
-    let deadline = client.send_next();
+    let setup = client.setup();
 
-    let until_ready = deadline.duration_since(Instant::now());
+    if setup.is_empty() {
+        return Some(client);
+    }
+
+    if let Ok(bytes_sent) = sender::send(&mut client.tcp_stream_mut(), &setup).await {
+        if let Some(bandwidth) = bandwidth {
+            // not worth making a new client wait for
+            bandwidth
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .consume(bytes_sent);
+        }
+
+        *client.bytes_sent_mut() += bytes_sent;
 
-    event!(Level::TRACE, addr = ?client.addr(), ?until_ready, "Scheduled client");
+        statistics_sender
+            .send(StatisticsMessage::BytesSent(bytes_sent))
+            .expect("Channel should always exist");
 
-    tokio::select! {
-        biased;
-        () = cancellation_token.cancelled() => {
-            // abandon
-            return None;
-        },
-        () = sleep_until(deadline) => {}
+        Some(client)
+    } else {
+        statistics_sender
+            .send(StatisticsMessage::LostClient)
+            .expect("Channel should always exist");
+
+        None
+    }
+}
+
+/// Hands what the peer sent to the protocol, logging anything it captured.
+async fn receive_from_client<S>(client: &mut Client<S>) -> Result<(), std::io::Error>
+where
+    S: tokio::io::AsyncRead + std::marker::Unpin,
+{
+    let mut buffer = [0_u8; RECEIVE_BUFFER_SIZE];
+
+    let received = protocol::receive(client.tcp_stream_mut(), &mut buffer).await?;
+
+    let Some(data) = buffer.get(..received).filter(|data| !data.is_empty()) else {
+        return Ok(());
+    };
+
+    let captures = client.receive(data);
+    log_captures(client, captures);
+
+    Ok(())
+}
+
+fn log_captures<S>(client: &Client<S>, captures: Vec<Capture>) {
+    for capture in captures {
+        event!(
+            Level::INFO,
+            addr = ?client.addr(),
+            session_id = client.session_id(),
+            protocol = %client.treatment().protocol,
+            kind = capture.kind,
+            value = capture.value,
+            "Captured",
+        );
+    }
+}
+
+async fn process_client<S>(
+    mut client: Client<S>,
+    statistics_sender: &UnboundedSender<StatisticsMessage>,
+    offenders_sender: Option<&UnboundedSender<OffenderMessage>>,
+    bandwidth: Option<&SharedTokenBucket>,
+) -> Option<Client<S>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWriteExt + std::marker::Unpin + std::fmt::Debug,
+{
+    if client
+        .expires_at()
+        .is_some_and(|expires_at| expires_at <= Instant::now())
+    {
+        event!(
+            Level::INFO,
+            addr = ?client.addr(),
+            rule = client.treatment().rule.as_deref(),
+            "Client lifetime over, releasing",
+        );
+
+        // Client will be dropped, connections terminated by libc::close
+        // and permit will be returned
+        return None;
+    }
+
+    if let Err(error) = receive_from_client(&mut client).await {
+        event!(Level::INFO, addr = ?client.addr(), ?error, "Failed to receive from client, client gone");
+
+        statistics_sender
+            .send(StatisticsMessage::LostClient)
+            .expect("Channel should always exist");
+
+        return None;
+    }
+
+    // we reserve what the protocol will send
+    let bytes = client.tick();
+
+    let captures = client.take_captures();
+    log_captures(&client, captures);
+
+    let mut reserved = 0;
+
+    if let Some(bandwidth) = bandwidth {
+        let now = Instant::now();
+
+        let taken = bandwidth
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take(bytes.len(), now);
+
+        match taken {
+            Ok(taken) => reserved = taken,
+            Err(wait) => {
+                event!(Level::DEBUG, addr = ?client.addr(), ?wait, "Bandwidth exhausted, deferring client");
+
+                statistics_sender
+                    .send(StatisticsMessage::DeferredTick)
+                    .expect("Channel should always exist");
+
+                client.defer(bytes);
+
+                // stretch the delay, the client keeps its place
+                *client.delay_mut() += wait;
+                *client.send_next_mut() = now + wait;
+
+                return Some(client);
+            },
+        }
     }
 
     statistics_sender
         .send(StatisticsMessage::ProcessedClient)
         .expect("Channel should always exist");
 
-    event!(Level::DEBUG, addr = ?client.addr(), "Processing client");
+    event!(Level::DEBUG, addr = ?client.addr(), rule = client.treatment().rule.as_deref(), "Processing client");
+
+    let result = sender::send(&mut client.tcp_stream_mut(), &bytes).await;
+
+    if let Some(bandwidth) = bandwidth {
+        bandwidth
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .settle(reserved, result.unwrap_or(0));
+    }
+
+    if let Ok(bytes_sent) = result {
+        // the delay we just waited
+        let delay = client.delay();
 
-    if let Ok(bytes_sent) =
-        sender::sendline(&mut client.tcp_stream_mut(), max_line_length.get().into()).await
-    {
         *client.bytes_sent_mut() += bytes_sent;
         *client.time_spent_mut() += delay;
 
@@ -99,8 +266,17 @@ where
                 .expect("Channel should always exist");
         }
 
+        if let Some(offenders_sender) = offenders_sender {
+            offenders_sender
+                .send(OffenderMessage::TimeSpent(client.addr().ip(), delay))
+                .expect("Channel should always exist");
+        }
+
         // and delay again
-        *client.send_next_mut() = Instant::now() + delay;
+        let next_delay = client.next_delay();
+
+        *client.delay_mut() = next_delay;
+        *client.send_next_mut() = Instant::now() + next_delay;
 
         // Done processing, return
         Some(client)
@@ -1,20 +1,75 @@
-use std::num::{NonZeroU8, NonZeroU16, NonZeroU32};
+use std::num::{NonZeroU8, NonZeroU16, NonZeroU32, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::Duration;
 
+use color_eyre::eyre::{self, Context as _};
+use serde::Deserialize;
 use tracing::{Level, event};
 
+use crate::line::shape::{Alphabet, LineLength};
+use crate::line::{Corpus, LineGenerator};
+use crate::protocol::Protocol;
+use crate::rules::Rule;
+use crate::schedule::Schedule;
+
 pub const DEFAULT_PORT: NonZeroU16 = NonZeroU16::new(2223).unwrap();
 pub const DEFAULT_DELAY_MS: NonZeroU32 = NonZeroU32::new(10000).unwrap();
-pub const DEFAULT_MAX_LINE_LENGTH: NonZeroU8 = NonZeroU8::new(32).unwrap();
+pub const DEFAULT_MAX_LINE_LENGTH: NonZeroU16 = NonZeroU16::new(32).unwrap();
+/// Only the version line is limited to 255 (RFC 4253 4.2), the lines before it aren't. We stop at
+/// the largest packet an SSH client has to accept (RFC 4253 6.1). SSH listeners stop earlier, see
+/// [`Protocol::max_line_length`].
+pub const MAX_LINE_LENGTH_LIMIT: u16 = 35000;
 pub const DEFAULT_MAX_CLIENTS: NonZeroU8 = NonZeroU8::new(64).unwrap();
+pub const DEFAULT_HISTORY_SIZE: NonZeroUsize = NonZeroUsize::new(10000).unwrap();
+pub const DEFAULT_REPEAT_OFFENDER_VISITS: NonZeroU32 = NonZeroU32::new(2).unwrap();
+pub const DEFAULT_OFFENDERS_THRESHOLD_MINUTES: u64 = 10;
+pub const DEFAULT_OFFENDERS_TIMEOUT_SECONDS: u64 = 86400;
+pub const DEFAULT_OFFENDERS_INTERVAL_SECONDS: u64 = 60;
+pub const DEFAULT_OFFENDERS_COMMAND_TIMEOUT_SECONDS: u64 = 30;
+pub const DEFAULT_OFFENDERS_SET: &str = "endless_ssh_offenders";
+pub const DEFAULT_OFFENDERS_NFT_TABLE: &str = "endless_ssh";
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Config {
+    /// What the random lines are made of.
+    pub alphabet: Alphabet,
+    /// Burst size of the bandwidth cap in bytes, `max_bandwidth` when not set.
+    pub bandwidth_burst: Option<NonZeroU32>,
     pub bind_family: BindFamily,
+    /// Lines to send instead of random ones.
+    pub corpus: Option<Arc<Corpus>>,
     pub delay: Duration,
+    /// Varies everything a scanner could fingerprint, per connection.
+    pub disguise: bool,
+    pub history_file: Option<PathBuf>,
+    pub history_size: NonZeroUsize,
+    /// How long the random lines are, up to `max_line_length`.
+    pub line_length: LineLength,
+    /// Where the lines come from, unless a rule says otherwise.
+    pub line_generator: LineGenerator,
+    /// From the config file, replaces `port` when not empty.
+    pub listeners: Vec<ListenerConfig>,
+    /// Bytes per second sent to all clients combined.
+    pub max_bandwidth: Option<NonZeroU32>,
     pub max_clients: NonZeroU8,
-    pub max_line_length: NonZeroU8,
+    /// Repeat offenders are exempt.
+    pub max_clients_per_ip: Option<NonZeroU8>,
+    pub max_line_length: NonZeroU16,
+    pub offenders: Option<OffendersConfig>,
     pub port: NonZeroU16,
+    /// Delay for repeat offenders, `delay` when not set.
+    pub repeat_offender_delay: Option<Duration>,
+    /// Visits (this one included) after which a source is a repeat offender.
+    pub repeat_offender_visits: NonZeroU32,
+    /// Slots only repeat offenders can take.
+    pub reserved_slots: u8,
+    /// From the config file, in order.
+    pub rules: Vec<Rule>,
+    /// From the config file, always active when not set.
+    pub schedule: Option<Schedule>,
+    /// Makes every session's random stream reproducible from it and the session id.
+    pub seed: Option<u64>,
 }
 
 impl Default for Config {
@@ -40,22 +95,245 @@ impl std::fmt::Display for BindFamily {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, clap::ValueEnum)]
+pub enum OffendersFormat {
+    /// A script for `nft -f`.
+    Nft,
+    /// A file for `ipset restore`.
+    Ipset,
+}
+
+impl std::fmt::Display for OffendersFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            OffendersFormat::Nft => write!(f, "nft"),
+            OffendersFormat::Ipset => write!(f, "ipset"),
+        }
+    }
+}
+
+/// Where and how we export the sources that spent too long in the tarpit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OffendersConfig {
+    /// File that gets (atomically) replaced on every write.
+    pub path: PathBuf,
+    pub format: OffendersFormat,
+    /// Time a source needs to spend in the tarpit before it becomes an offender.
+    pub threshold: Duration,
+    /// How long an offender stays in the set after it was last seen.
+    pub entry_timeout: Duration,
+    /// How often we write the file.
+    pub interval: Duration,
+    /// Name of the set, suffixed with `_v4` and `_v6`.
+    pub set_name: String,
+    /// Only used for `OffendersFormat::Nft`.
+    pub nft_table: String,
+    /// Executed with `sh -c` after each write.
+    pub command: Option<String>,
+    /// After this, the command is killed.
+    pub command_timeout: Duration,
+}
+
 impl Config {
     pub fn new() -> Self {
         Self {
             port: DEFAULT_PORT,
             delay: Duration::from_millis(DEFAULT_DELAY_MS.get().into()),
             max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            line_length: LineLength::default(),
+            line_generator: LineGenerator::default(),
+            alphabet: Alphabet::default(),
             max_clients: DEFAULT_MAX_CLIENTS,
             bind_family: BindFamily::DualStack,
+            corpus: None,
+            disguise: false,
+            offenders: None,
+            history_file: None,
+            history_size: DEFAULT_HISTORY_SIZE,
+            listeners: Vec::new(),
+            repeat_offender_visits: DEFAULT_REPEAT_OFFENDER_VISITS,
+            repeat_offender_delay: None,
+            reserved_slots: 0,
+            max_clients_per_ip: None,
+            max_bandwidth: None,
+            bandwidth_burst: None,
+            rules: Vec::new(),
+            schedule: None,
+            seed: None,
+        }
+    }
+
+    /// Where we listen, and what we pretend to be there.
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        if self.listeners.is_empty() {
+            vec![ListenerConfig {
+                port: self.port,
+                protocol: Protocol::Ssh,
+            }]
+        } else {
+            self.listeners.clone()
         }
     }
 
     pub fn log(&self) {
-        event!(Level::INFO, "Port: {}", self.port);
+        for listener in self.listeners() {
+            event!(
+                Level::INFO,
+                "Port: {} ({})",
+                listener.port,
+                listener.protocol
+            );
+        }
+
         event!(Level::INFO, "Delay: {}ms", self.delay.as_millis());
         event!(Level::INFO, "MaxLineLength: {}", self.max_line_length);
+        event!(Level::INFO, "LineLength: {}", self.line_length);
+        event!(Level::INFO, "Alphabet: {}", self.alphabet);
+        event!(Level::INFO, "LineGenerator: {}", self.line_generator);
         event!(Level::INFO, "MaxClients: {}", self.max_clients);
         event!(Level::INFO, "BindFamily: {}", self.bind_family);
+        event!(Level::INFO, "HistorySize: {}", self.history_size);
+
+        if self.disguise {
+            event!(Level::INFO, "Disguise: on");
+        }
+
+        if let Some(seed) = self.seed {
+            event!(Level::INFO, "Seed: {}", seed);
+        }
+
+        if let Some(ref corpus) = self.corpus {
+            event!(Level::INFO, "Corpus: {} lines", corpus.len());
+        }
+
+        if let Some(ref history_file) = self.history_file {
+            event!(Level::INFO, "HistoryFile: {}", history_file.display());
+        }
+
+        event!(
+            Level::INFO,
+            "RepeatOffenderVisits: {}",
+            self.repeat_offender_visits
+        );
+
+        if let Some(repeat_offender_delay) = self.repeat_offender_delay {
+            event!(
+                Level::INFO,
+                "RepeatOffenderDelay: {}ms",
+                repeat_offender_delay.as_millis()
+            );
+        }
+
+        event!(Level::INFO, "ReservedSlots: {}", self.reserved_slots);
+
+        if let Some(max_bandwidth) = self.max_bandwidth {
+            event!(
+                Level::INFO,
+                "MaxBandwidth: {} bytes/s, burst: {} bytes",
+                max_bandwidth,
+                self.bandwidth_burst.unwrap_or(max_bandwidth)
+            );
+        }
+
+        if let Some(max_clients_per_ip) = self.max_clients_per_ip {
+            event!(Level::INFO, "MaxClientsPerIp: {}", max_clients_per_ip);
+        }
+
+        for rule in &self.rules {
+            event!(Level::INFO, "Rule: {}", rule.name);
+        }
+
+        if let Some(ref schedule) = self.schedule {
+            event!(
+                Level::INFO,
+                "Schedule: {} window(s), fallback: {}, on close: {:?}",
+                schedule.active.len(),
+                schedule.fallback,
+                schedule.on_close,
+            );
+        }
+
+        if let Some(ref offenders) = self.offenders {
+            event!(
+                Level::INFO,
+                "Offenders: {} ({}), threshold: {}s, entry timeout: {}s, interval: {}s",
+                offenders.path.display(),
+                offenders.format,
+                offenders.threshold.as_secs(),
+                offenders.entry_timeout.as_secs(),
+                offenders.interval.as_secs(),
+            );
+        }
+    }
+}
+
+/// A port we listen on, from the config file.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    pub port: NonZeroU16,
+    #[serde(default)]
+    pub protocol: Protocol,
+}
+
+/// Everything that's too elaborate for the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    #[serde(default)]
+    pub listeners: Vec<ListenerConfig>,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    pub schedule: Option<Schedule>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, eyre::Report> {
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read config file {}", path.display()))?;
+
+        Self::parse(&contents)
+            .wrap_err_with(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, eyre::Report> {
+        let config_file: Self = toml::from_str(contents)?;
+
+        for rule in &config_file.rules {
+            if rule.action.max_line_length.is_some_and(|max_line_length| {
+                !(3..=MAX_LINE_LENGTH_LIMIT).contains(&max_line_length.get())
+            }) {
+                return Err(eyre::Report::msg(format!(
+                    "Rule {:?}: max_line_length needs to be between 3 and {}",
+                    rule.name, MAX_LINE_LENGTH_LIMIT
+                )));
+            }
+        }
+
+        for (index, listener) in config_file.listeners.iter().enumerate() {
+            if config_file
+                .listeners
+                .iter()
+                .skip(index + 1)
+                .any(|other| other.port == listener.port)
+            {
+                return Err(eyre::Report::msg(format!(
+                    "Port {} is listed more than once",
+                    listener.port
+                )));
+            }
+        }
+
+        if config_file
+            .schedule
+            .as_ref()
+            .is_some_and(|schedule| schedule.active.is_empty())
+        {
+            return Err(eyre::Report::msg(
+                "Schedule needs at least one active window",
+            ));
+        }
+
+        Ok(config_file)
     }
 }
@@ -0,0 +1,117 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use color_eyre::eyre;
+use rand::RngExt as _;
+use serde::Deserialize;
+
+use crate::random::with_rng;
+
+/// How long we wait between two lines, written as milliseconds: `10000` or `5000-20000`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub enum DelayStrategy {
+    /// Always the same delay.
+    Fixed(Duration),
+    /// Uniformly random between the two, inclusive.
+    Uniform(Duration, Duration),
+}
+
+impl DelayStrategy {
+    pub fn next_delay(&self) -> Duration {
+        match *self {
+            DelayStrategy::Fixed(delay) => delay,
+            DelayStrategy::Uniform(min, max) => with_rng(|rng| rng.random_range(min..=max)),
+        }
+    }
+}
+
+impl FromStr for DelayStrategy {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse = |milliseconds: &str| {
+            milliseconds
+                .trim()
+                .parse::<u64>()
+                .map(Duration::from_millis)
+                .map_err(|error| {
+                    eyre::Report::new(error).wrap_err(format!("Invalid delay {:?}", value))
+                })
+        };
+
+        match value.split_once('-') {
+            Some((min, max)) => {
+                let (min, max) = (parse(min)?, parse(max)?);
+
+                if min > max {
+                    return Err(eyre::Report::msg(format!(
+                        "Invalid delay {:?}, minimum is larger than maximum",
+                        value
+                    )));
+                }
+
+                Ok(DelayStrategy::Uniform(min, max))
+            },
+            None => Ok(DelayStrategy::Fixed(parse(value)?)),
+        }
+    }
+}
+
+impl TryFrom<String> for DelayStrategy {
+    type Error = eyre::Report;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl std::fmt::Display for DelayStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            DelayStrategy::Fixed(delay) => write!(f, "{}ms", delay.as_millis()),
+            DelayStrategy::Uniform(min, max) => {
+                write!(f, "{}-{}ms", min.as_millis(), max.as_millis())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::delay::DelayStrategy;
+
+    #[test]
+    fn parses_fixed_and_uniform() {
+        assert_eq!(
+            "10000".parse::<DelayStrategy>().unwrap(),
+            DelayStrategy::Fixed(Duration::from_secs(10))
+        );
+        assert_eq!(
+            "5000-20000".parse::<DelayStrategy>().unwrap(),
+            DelayStrategy::Uniform(Duration::from_secs(5), Duration::from_secs(20))
+        );
+
+        #[expect(unused_must_use, reason = "Testing")]
+        "20000-5000".parse::<DelayStrategy>().unwrap_err();
+    }
+
+    #[test]
+    fn uniform_stays_in_range() {
+        let strategy = DelayStrategy::Uniform(Duration::from_secs(5), Duration::from_secs(6));
+
+        for _ in 0..100 {
+            let delay = strategy.next_delay();
+
+            assert!(
+                (Duration::from_secs(5)..=Duration::from_secs(6)).contains(&delay),
+                "{:?} out of range",
+                delay
+            );
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::num::NonZeroU16;
+use std::time::Duration;
+
+use rand::RngExt as _;
+
+use crate::delay::DelayStrategy;
+use crate::line::LineGenerator;
+use crate::line::shape::{Alphabet, LineLength};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// Like `/etc/issue.net`, some servers send one of these before their version (RFC 4253 4.2).
+const PRE_BANNERS: &[&[&str]] = &[
+    &["Ubuntu 22.04.4 LTS"],
+    &["Debian GNU/Linux 12"],
+    &[
+        "Authorized access only!",
+        "If you are not authorized to access or use this system, disconnect now!",
+    ],
+    &[
+        "********************************************************************",
+        "* This system is for the use of authorized users only. Usage of    *",
+        "* this system may be monitored and recorded by system personnel.   *",
+        "********************************************************************",
+    ],
+    &[
+        "WARNING: Unauthorized access to this system is forbidden and will be",
+        "prosecuted by law. By accessing this system, you agree that your actions",
+        "may be monitored if unauthorized usage is suspected.",
+    ],
+];
+
+/// What we pick line lengths up to, disguised: the longest a version line can be (RFC 4253 4.2).
+/// Real servers' lines are no longer, though up to `MAX_LINE_LENGTH_LIMIT` can be configured.
+const MAX_DISGUISED_LINE_LENGTH: u16 = 255;
+
+/// Disguised, the first line comes about as fast as a real server's banner.
+const MAX_FIRST_DELAY: Duration = Duration::from_secs(2);
+
+/// Gets a connection going: disguised, its treatment gets its own delays, line shapes and maybe a
+/// pre-banner, around what was configured. Returns the delay before the first line.
+pub fn start(treatment: &mut Treatment) -> Duration {
+    if !treatment.disguise {
+        return treatment.delay.next_delay();
+    }
+
+    vary(treatment);
+
+    let delay = treatment.delay.next_delay();
+
+    with_rng(|rng| rng.random_range(Duration::ZERO..=(delay / 8).min(MAX_FIRST_DELAY)))
+}
+
+fn vary(treatment: &mut Treatment) {
+    with_rng(|rng| {
+        // this connection's pace, from a third to three times what was configured, and lines come
+        // at anything from half to one and a half times that
+        let (min, max) = match treatment.delay {
+            DelayStrategy::Fixed(delay) => (delay, delay),
+            DelayStrategy::Uniform(min, max) => (min, max),
+        };
+
+        let scale = 3.0_f64.powf(rng.random_range(-1.0..1.0));
+
+        treatment.delay =
+            DelayStrategy::Uniform(min.mul_f64(scale / 2.0), max.mul_f64(scale * 1.5));
+
+        treatment.pre_banner = if rng.random() {
+            PRE_BANNERS[rng.random_range(0..PRE_BANNERS.len())]
+        } else {
+            &[]
+        };
+
+        let longest_pre_banner = treatment
+            .pre_banner
+            .iter()
+            .map(|line| line.len() + 2)
+            .max()
+            .unwrap_or(3);
+
+        let configured = treatment
+            .max_line_length
+            .get()
+            .min(MAX_DISGUISED_LINE_LENGTH);
+
+        // the pre-banner has to fit, nothing else needs to be that long
+        let min_line_length = configured
+            .max(3)
+            .max(u16::try_from(longest_pre_banner).unwrap_or(u16::MAX));
+        let max_line_length = configured
+            .saturating_mul(4)
+            .clamp(min_line_length, MAX_DISGUISED_LINE_LENGTH);
+
+        treatment.max_line_length =
+            NonZeroU16::new(rng.random_range(min_line_length..=max_line_length))
+                .expect("At least 3");
+
+        let max_length = f64::from(treatment.max_line_length.get());
+
+        treatment.line_length = match rng.random_range(0..3) {
+            0 => LineLength::Uniform,
+            1 => {
+                let mean = rng.random_range((max_length / 3.0).max(3.0)..=max_length);
+
+                LineLength::Normal {
+                    mean,
+                    std_dev: rng.random_range(1.0..=mean / 2.0 + 1.0),
+                }
+            },
+            _ => LineLength::Zipf {
+                exponent: rng.random_range(0.3..1.2),
+            },
+        };
+
+        treatment.alphabet = match rng.random_range(0..4) {
+            0 => Alphabet::Printable,
+            1 => Alphabet::Alphanumeric,
+            2 => Alphabet::Hex,
+            _ => Alphabet::Base64,
+        };
+
+        if treatment.corpus.is_some() {
+            treatment.line_generator = match rng.random_range(0..3) {
+                0 => LineGenerator::Random,
+                1 => LineGenerator::Corpus,
+                _ => LineGenerator::Markov,
+            };
+        }
+    });
+}
+
+/// Heuristics scanners use to tell the tarpit from a real server, run against what it sends.
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::config::Config;
+    use crate::disguise::start;
+    use crate::protocol::Protocol;
+    use crate::random::{in_session, session_rng};
+    use crate::rules::Treatment;
+
+    const SEED: u64 = 4253;
+    const SESSIONS: u64 = 200;
+    /// What a scanner waits for, at most.
+    const LINES: usize = 10;
+
+    /// When each line arrived, since connecting, and what it was.
+    type Transcript = Vec<(Duration, Vec<u8>)>;
+
+    fn transcript(config: &Config, session_id: u64) -> Transcript {
+        let mut rng = session_rng(Some(SEED), session_id);
+
+        let mut treatment = Treatment::defaults(config, Protocol::Ssh, false);
+        let mut session = Protocol::Ssh.session();
+
+        in_session(&mut rng, || {
+            let mut at = start(&mut treatment);
+
+            let mut transcript = Vec::new();
+
+            for _ in 0..LINES {
+                transcript.push((at, session.tick(&treatment)));
+
+                at += treatment.delay.next_delay();
+            }
+
+            transcript
+        })
+    }
+
+    /// Real servers answer right away, the tarpit takes its delay.
+    fn slow_first_line(transcript: &Transcript) -> bool {
+        transcript[0].0 >= Duration::from_secs(5)
+    }
+
+    /// Lines like clockwork.
+    fn constant_interval(transcript: &Transcript) -> bool {
+        let intervals = intervals(transcript);
+
+        let mean =
+            intervals.iter().sum::<f64>() / f64::from(u32::try_from(intervals.len()).unwrap());
+        let variance = intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>()
+            / f64::from(u32::try_from(intervals.len()).unwrap());
+
+        variance.sqrt() / mean < 0.05
+    }
+
+    /// What the original sends: short lines of random printable characters.
+    fn random_short_lines(transcript: &Transcript) -> bool {
+        transcript.iter().all(|&(_, ref line)| {
+            line.len() <= 32
+                && line
+                    .strip_suffix(b"\r\n")
+                    .is_some_and(|text| text.iter().all(|&byte| (32..=126).contains(&byte)))
+        })
+    }
+
+    /// Two connections to the same host, paced the same.
+    fn same_pace(first: &Transcript, second: &Transcript) -> bool {
+        let pace = |transcript: &Transcript| intervals(transcript).iter().sum::<f64>();
+
+        (pace(first) / pace(second) - 1.0).abs() < 0.05
+    }
+
+    fn intervals(transcript: &Transcript) -> Vec<f64> {
+        transcript
+            .windows(2)
+            .map(|pair| pair[1].0.saturating_sub(pair[0].0).as_secs_f64())
+            .collect()
+    }
+
+    /// Of the sessions, how many each heuristic flags.
+    fn detections(config: &Config) -> [u64; 4] {
+        let mut detections = [0; 4];
+
+        for session_id in 0..SESSIONS {
+            let first = transcript(config, session_id);
+            let second = transcript(config, session_id + SESSIONS);
+
+            for (detected, count) in [
+                slow_first_line(&first),
+                constant_interval(&first),
+                random_short_lines(&first),
+                same_pace(&first, &second),
+            ]
+            .into_iter()
+            .zip(&mut detections)
+            {
+                *count += u64::from(detected);
+            }
+        }
+
+        detections
+    }
+
+    #[test]
+    fn heuristics_spot_the_plain_tarpit() {
+        let detections = detections(&Config::default());
+
+        assert!(
+            detections.iter().all(|&count| count == SESSIONS),
+            "Every session detected: {:?}",
+            detections
+        );
+    }
+
+    #[test]
+    fn heuristics_fail_on_the_disguise() {
+        let config = Config {
+            disguise: true,
+            ..Config::default()
+        };
+
+        let detections = detections(&config);
+
+        // a session that happens to draw short printable lines still looks like the original
+        assert!(
+            detections.iter().all(|&count| count <= SESSIONS / 10),
+            "At most one in ten sessions detected: {:?}",
+            detections
+        );
+    }
+}
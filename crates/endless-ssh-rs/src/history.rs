@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use color_eyre::eyre::{self, Context as _, OptionExt as _};
+use time::OffsetDateTime;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::utils::fs::write_atomically;
+
+type StdDuration = std::time::Duration;
+
+pub type SharedHistory = Arc<Mutex<History>>;
+
+const PERSIST_INTERVAL: StdDuration = StdDuration::from_mins(5);
+
+/// What we remember about a source address.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Visitor {
+    visits: u64,
+    time_trapped: StdDuration,
+    last_seen: OffsetDateTime,
+    /// Connections of this source currently in the tarpit. Not persisted.
+    active: usize,
+}
+
+impl Visitor {
+    pub fn visits(&self) -> u64 {
+        self.visits
+    }
+
+    #[cfg_attr(not(test), expect(unused, reason = "Consistency with other props"))]
+    pub fn time_trapped(&self) -> StdDuration {
+        self.time_trapped
+    }
+
+    #[cfg_attr(not(test), expect(unused, reason = "Consistency with other props"))]
+    pub fn last_seen(&self) -> OffsetDateTime {
+        self.last_seen
+    }
+
+    pub fn active(&self) -> usize {
+        self.active
+    }
+}
+
+/// Bounded memory of the sources that visited the tarpit, keyed by address.
+/// When full, the source we've seen least recently (and that isn't connected) is forgotten.
+#[derive(Debug)]
+pub struct History {
+    capacity: NonZeroUsize,
+    visitors: BTreeMap<IpAddr, Visitor>,
+}
+
+impl History {
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            capacity,
+            visitors: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the history from `path`, starting empty when the file doesn't exist (yet).
+    pub async fn load(path: &Path, capacity: NonZeroUsize) -> Result<Self, eyre::Report> {
+        match tokio::fs::read_to_string(path).await {
+            Ok(contents) => {
+                let history = Self::parse(&contents, capacity)
+                    .wrap_err_with(|| format!("Failed to parse history {}", path.display()))?;
+
+                event!(Level::INFO, path = %path.display(), visitors = history.len(), "History loaded");
+
+                Ok(history)
+            },
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                event!(Level::INFO, path = %path.display(), "No history found, starting fresh");
+
+                Ok(Self::new(capacity))
+            },
+            Err(error) => Err(eyre::Report::new(error)
+                .wrap_err(format!("Failed to read history {}", path.display()))),
+        }
+    }
+
+    pub async fn save(history: &SharedHistory, path: &Path) -> Result<(), eyre::Report> {
+        // don't hold the lock while writing
+        let contents = history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .serialize();
+
+        write_atomically(path, contents).await
+    }
+
+    pub fn get(&self, ip: IpAddr) -> Option<&Visitor> {
+        self.visitors.get(&ip.to_canonical())
+    }
+
+    pub fn len(&self) -> usize {
+        self.visitors.len()
+    }
+
+    /// Registers a new connection of `ip` that was let into the tarpit.
+    pub fn connect(&mut self, ip: IpAddr, now: OffsetDateTime) {
+        let visitor = self.visitors.entry(ip.to_canonical()).or_insert(Visitor {
+            visits: 0,
+            time_trapped: StdDuration::ZERO,
+            last_seen: now,
+            active: 0,
+        });
+
+        visitor.visits += 1;
+        visitor.active += 1;
+        visitor.last_seen = now;
+
+        self.evict();
+    }
+
+    /// Registers that a connection of `ip` left the tarpit after `time_trapped`.
+    pub fn disconnect(&mut self, ip: IpAddr, time_trapped: StdDuration, now: OffsetDateTime) {
+        let Some(visitor) = self.visitors.get_mut(&ip.to_canonical()) else {
+            return;
+        };
+
+        visitor.time_trapped += time_trapped;
+        visitor.active = visitor.active.saturating_sub(1);
+        visitor.last_seen = now;
+    }
+
+    fn evict(&mut self) {
+        while self.visitors.len() > self.capacity.get() {
+            let least_recently_seen = self
+                .visitors
+                .iter()
+                .filter(|&(_, visitor)| visitor.active == 0)
+                .min_by_key(|&(_, visitor)| visitor.last_seen)
+                .map(|(&ip, _)| ip);
+
+            let Some(ip) = least_recently_seen else {
+                // everyone is connected, we'll shrink once they leave
+                break;
+            };
+
+            self.visitors.remove(&ip);
+        }
+    }
+
+    /// One visitor per line: `address visits time_trapped_ms last_seen_unix`.
+    fn serialize(&self) -> String {
+        let mut contents = String::new();
+
+        for (ip, visitor) in &self.visitors {
+            writeln!(
+                contents,
+                "{} {} {} {}",
+                ip,
+                visitor.visits,
+                visitor.time_trapped.as_millis(),
+                visitor.last_seen.unix_timestamp()
+            )
+            .unwrap();
+        }
+
+        contents
+    }
+
+    fn parse(contents: &str, capacity: NonZeroUsize) -> Result<Self, eyre::Report> {
+        let mut history = Self::new(capacity);
+
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let visitor = parse_line(line).wrap_err_with(|| format!("Line {}", index + 1))?;
+
+            history.visitors.insert(visitor.0, visitor.1);
+        }
+
+        history.evict();
+
+        Ok(history)
+    }
+}
+
+fn parse_line(line: &str) -> Result<(IpAddr, Visitor), eyre::Report> {
+    let mut fields = line.split_whitespace();
+
+    let mut next = |name: &str| fields.next().ok_or_eyre(format!("Missing {}", name));
+
+    let ip: IpAddr = next("address")?.parse()?;
+    let visits = next("visits")?.parse()?;
+    let time_trapped = StdDuration::from_millis(next("time trapped")?.parse()?);
+    let last_seen = OffsetDateTime::from_unix_timestamp(next("last seen")?.parse()?)?;
+
+    Ok((
+        ip.to_canonical(),
+        Visitor {
+            visits,
+            time_trapped,
+            last_seen,
+            active: 0,
+        },
+    ))
+}
+
+/// Periodically writes the history to `path`, and one final time when cancelled.
+pub async fn persist_history(
+    cancellation_token: CancellationToken,
+    history: SharedHistory,
+    path: PathBuf,
+) {
+    let mut persist_interval = tokio::time::interval(PERSIST_INTERVAL);
+    persist_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // the first tick completes immediately, nothing changed since we loaded
+    persist_interval.tick().await;
+
+    loop {
+        tokio::select! {
+            () = cancellation_token.cancelled() => {
+                break;
+            },
+            _ = persist_interval.tick() => {
+                if let Err(error) = History::save(&history, &path).await {
+                    event!(Level::ERROR, ?error, "Failed to persist history");
+                }
+            },
+        }
+    }
+
+    if let Err(error) = History::save(&history, &path).await {
+        event!(Level::ERROR, ?error, "Failed to persist history");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::num::NonZeroUsize;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use time::OffsetDateTime;
+
+    use crate::history::History;
+
+    fn at(unix_timestamp: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(unix_timestamp).unwrap()
+    }
+
+    #[test]
+    fn remembers_visits_across_connections() {
+        let mut history = History::new(NonZeroUsize::new(10).unwrap());
+
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+
+        history.connect(ip, at(1000));
+        history.disconnect(ip, Duration::from_mins(1), at(1060));
+        history.connect("::ffff:192.0.2.1".parse().unwrap(), at(5000));
+
+        let visitor = history.get(ip).unwrap();
+
+        assert_eq!(visitor.visits(), 2);
+        assert_eq!(visitor.active(), 1);
+        assert_eq!(visitor.time_trapped(), Duration::from_mins(1));
+        assert_eq!(visitor.last_seen(), at(5000));
+    }
+
+    #[test]
+    fn forgets_least_recently_seen_inactive_visitor() {
+        let mut history = History::new(NonZeroUsize::new(2).unwrap());
+
+        let oldest: IpAddr = "192.0.2.1".parse().unwrap();
+        let old_but_connected: IpAddr = "192.0.2.2".parse().unwrap();
+        let newest: IpAddr = "192.0.2.3".parse().unwrap();
+
+        history.connect(old_but_connected, at(500));
+        history.connect(oldest, at(1000));
+        history.disconnect(oldest, Duration::ZERO, at(1000));
+        history.connect(newest, at(2000));
+
+        assert_eq!(history.len(), 2);
+        assert!(history.get(oldest).is_none(), "Oldest should be evicted");
+        assert!(history.get(old_but_connected).is_some(), "Still connected");
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut history = History::new(NonZeroUsize::new(10).unwrap());
+
+        let ip: IpAddr = "2001:db8::1".parse().unwrap();
+
+        history.connect(ip, at(1000));
+        history.disconnect(ip, Duration::from_millis(1500), at(1002));
+
+        let serialized = history.serialize();
+
+        assert_eq!(serialized, "2001:db8::1 1 1500 1002\n");
+
+        let parsed = History::parse(&serialized, NonZeroUsize::new(10).unwrap()).unwrap();
+
+        let visitor = parsed.get(ip).unwrap();
+
+        assert_eq!(visitor.visits(), 1);
+        assert_eq!(visitor.active(), 0);
+        assert_eq!(visitor.time_trapped(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn rejects_broken_lines() {
+        let result = History::parse("192.0.2.1 1 1500\n", NonZeroUsize::new(10).unwrap());
+
+        #[expect(unused_must_use, reason = "Testing")]
+        result.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn persists_to_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("history");
+
+        let capacity = NonZeroUsize::new(10).unwrap();
+
+        let history = Arc::new(Mutex::new(History::load(&path, capacity).await.unwrap()));
+
+        history
+            .lock()
+            .unwrap()
+            .connect("192.0.2.1".parse().unwrap(), at(1000));
+
+        History::save(&history, &path).await.unwrap();
+
+        let loaded = History::load(&path, capacity).await.unwrap();
+
+        assert_eq!(loaded.len(), 1);
+    }
+}
@@ -1,10 +1,23 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use ::rand::distr::Distribution;
 use ::rand::distr::uniform::{SampleRange, SampleUniform};
+use color_eyre::eyre::{self, Context as _};
 use rand::RngExt as _;
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
+use serde::Deserialize;
+
+use crate::line::shape::{Alphabet, LineLength};
+use crate::random::with_rng;
+
+pub mod shape;
 
 mod get_random {
     #![expect(clippy::disallowed_types, reason = "Macro")]
 
+    use ::rand::distr::Distribution;
     use ::rand::distr::uniform::{SampleRange, SampleUniform};
     use mockall::automock;
 
@@ -14,6 +27,11 @@ mod get_random {
         where
             T: SampleUniform + 'static,
             R: SampleRange<T> + 'static;
+
+        fn sample<T, D>(&mut self, distribution: D) -> T
+        where
+            T: 'static,
+            D: Distribution<T> + 'static;
     }
 }
 
@@ -23,7 +41,7 @@ struct GenRange<R> {
     rng: R,
 }
 
-impl GetRandom for GenRange<ThreadRng> {
+impl GetRandom for GenRange<&mut StdRng> {
     fn gen_range<T, R>(&mut self, range: R) -> T
     where
         T: SampleUniform + 'static,
@@ -31,24 +49,299 @@ impl GetRandom for GenRange<ThreadRng> {
     {
         self.rng.random_range(range)
     }
+
+    fn sample<T, D>(&mut self, distribution: D) -> T
+    where
+        T: 'static,
+        D: Distribution<T> + 'static,
+    {
+        self.rng.sample(distribution)
+    }
+}
+
+/// Where the lines we send come from.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LineGenerator {
+    /// Random characters, see `randline`.
+    #[default]
+    Random,
+    /// Lines from the corpus file, see `corpus_line`.
+    Corpus,
+    /// New lines that look like the corpus file's, see `markov_line`.
+    Markov,
+}
+
+impl LineGenerator {
+    /// Random lines when there's no corpus to draw from.
+    pub fn line(
+        self,
+        corpus: Option<&Corpus>,
+        order: &CorpusOrder,
+        line_length: LineLength,
+        alphabet: &Alphabet,
+        maxlen: usize,
+    ) -> Vec<u8> {
+        match (self, corpus) {
+            (LineGenerator::Corpus, Some(corpus)) => corpus_line(corpus, order, maxlen),
+            (LineGenerator::Markov, Some(corpus)) => markov_line(&corpus.markov, maxlen),
+            (LineGenerator::Random, _) | (LineGenerator::Corpus | LineGenerator::Markov, None) => {
+                randline(line_length, alphabet, maxlen)
+            },
+        }
+    }
+
+    pub fn needs_corpus(self) -> bool {
+        match self {
+            LineGenerator::Random => false,
+            LineGenerator::Corpus | LineGenerator::Markov => true,
+        }
+    }
+}
+
+impl std::fmt::Display for LineGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            LineGenerator::Random => write!(f, "random"),
+            LineGenerator::Corpus => write!(f, "corpus"),
+            LineGenerator::Markov => write!(f, "markov"),
+        }
+    }
+}
+
+/// Lines of text to send, like fake log lines or an RFC, which look less like noise.
+#[derive(Eq, PartialEq)]
+pub struct Corpus {
+    lines: Vec<Vec<u8>>,
+    /// Trained on the lines right away, it's cheap enough.
+    markov: Markov,
+}
+
+impl std::fmt::Debug for Corpus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // it's in every client's treatment, which gets logged
+        f.debug_struct("Corpus")
+            .field("lines", &self.lines.len())
+            .field("markov_contexts", &self.markov.followers.len())
+            .finish()
+    }
+}
+
+impl Corpus {
+    pub fn load(path: &Path) -> Result<Self, eyre::Report> {
+        let contents = std::fs::read(path)
+            .wrap_err_with(|| format!("Failed to read corpus file {}", path.display()))?;
+
+        Self::parse(&contents).ok_or_else(|| {
+            eyre::Report::msg(format!("Corpus file {} has no lines", path.display()))
+        })
+    }
+
+    /// Without line endings, trailing whitespace and control characters, and without the lines
+    /// that are left empty.
+    pub fn parse(contents: &[u8]) -> Option<Self> {
+        let lines = contents
+            .split(|&byte| byte == b'\n')
+            .map(|line| {
+                line.trim_ascii_end()
+                    .iter()
+                    .copied()
+                    .filter(|byte| !byte.is_ascii_control())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>();
+
+        if lines.is_empty() {
+            return None;
+        }
+
+        let markov = Markov::train(&lines);
+
+        Some(Self { lines, markov })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+/// A session's shuffled order of the corpus' lines, so it sends every line before any repeats.
+#[derive(Default)]
+pub struct CorpusOrder {
+    /// Indices of the lines still to send, the next one last.
+    remaining: Mutex<Vec<usize>>,
+}
+
+impl CorpusOrder {
+    fn next_from(&self, rng: &mut impl GetRandom, len: usize) -> usize {
+        let mut remaining = self
+            .remaining
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if remaining.is_empty() {
+            *remaining = (0..len).collect();
+
+            // Fisher-Yates
+            for index in (1..len).rev() {
+                remaining.swap(index, rng.gen_range(0..index + 1));
+            }
+        }
+
+        remaining.pop().expect("A corpus has lines")
+    }
+}
+
+impl Clone for CorpusOrder {
+    fn clone(&self) -> Self {
+        let remaining = self
+            .remaining
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        Self {
+            remaining: Mutex::new(remaining.clone()),
+        }
+    }
+}
+
+impl std::fmt::Debug for CorpusOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let remaining = self
+            .remaining
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        f.debug_struct("CorpusOrder")
+            .field("remaining", &remaining.len())
+            .finish()
+    }
+}
+
+// where a session is in the corpus isn't part of how it's treated
+impl PartialEq for CorpusOrder {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
-pub fn randline(maxlen: usize) -> Vec<u8> {
-    randline_from(GenRange { rng: ::rand::rng() }, maxlen)
+impl Eq for CorpusOrder {}
+
+/// Characters in a line that decide what the next one is.
+const MARKOV_ORDER: usize = 3;
+/// Before a line's first character, a control character `Corpus::parse` drops.
+const MARKOV_START: u8 = 0;
+/// After a line's last character.
+const MARKOV_END: u8 = b'\n';
+
+/// A character-level Markov chain: for every `MARKOV_ORDER` characters in the corpus, what
+/// followed them. Repeats and all, so the frequent ones are picked more often.
+#[derive(Debug, Eq, PartialEq)]
+struct Markov {
+    followers: BTreeMap<[u8; MARKOV_ORDER], Vec<u8>>,
 }
 
-fn randline_from(mut rng: impl GetRandom, maxlen: usize) -> Vec<u8> {
-    // original did 3 + rand(s) % (maxlen - 2)
-    // so if rand(2) was 47, maxlen 50, the outcome is 3 + (47 % 48)
-    // we have a length of 50
-    // with a range we don't need to do - 2
-    let len = rng.gen_range(3..=maxlen);
+impl Markov {
+    fn train(lines: &[Vec<u8>]) -> Self {
+        let mut followers = BTreeMap::<_, Vec<u8>>::new();
+
+        for line in lines {
+            let mut context = [MARKOV_START; MARKOV_ORDER];
+
+            for &character in line.iter().chain(&[MARKOV_END]) {
+                followers.entry(context).or_default().push(character);
+
+                context.rotate_left(1);
+                context[MARKOV_ORDER - 1] = character;
+            }
+        }
+
+        Self { followers }
+    }
+}
+
+pub fn randline(line_length: LineLength, alphabet: &Alphabet, maxlen: usize) -> Vec<u8> {
+    with_rng(|rng| randline_from(GenRange { rng }, line_length, alphabet, maxlen))
+}
+
+pub fn corpus_line(corpus: &Corpus, order: &CorpusOrder, maxlen: usize) -> Vec<u8> {
+    with_rng(|rng| corpus_line_from(GenRange { rng }, corpus, order, maxlen))
+}
+
+fn corpus_line_from(
+    mut rng: impl GetRandom,
+    corpus: &Corpus,
+    order: &CorpusOrder,
+    maxlen: usize,
+) -> Vec<u8> {
+    let line = &corpus.lines[order.next_from(&mut rng, corpus.lines.len())];
+
+    // cut off to make room for CR LF, which is at least 1 left, as maxlen is at least 3
+    let len = line.len().min(maxlen - 2);
+
+    let mut buffer = Vec::with_capacity(len + 2);
+
+    buffer.extend_from_slice(&line[..len]);
+    buffer.extend_from_slice(&[13_u8, 10]);
+
+    // ensure start doesn't begin with "SSH-"
+    if buffer.starts_with(b"SSH-") {
+        buffer[0] = b'X';
+    }
+
+    buffer
+}
+
+fn markov_line(markov: &Markov, maxlen: usize) -> Vec<u8> {
+    with_rng(|rng| markov_line_from(GenRange { rng }, markov, maxlen))
+}
+
+fn markov_line_from(mut rng: impl GetRandom, markov: &Markov, maxlen: usize) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(maxlen);
+    let mut context = [MARKOV_START; MARKOV_ORDER];
+
+    // make room for CR LF, which is at least 1 left, as maxlen is at least 3
+    while buffer.len() < maxlen - 2 {
+        // every context we get to was followed by something, if only the end
+        let Some(followers) = markov.followers.get(&context) else {
+            break;
+        };
+
+        let character = followers[rng.gen_range(0..followers.len())];
+
+        if character == MARKOV_END {
+            break;
+        }
+
+        buffer.push(character);
+
+        context.rotate_left(1);
+        context[MARKOV_ORDER - 1] = character;
+    }
+
+    buffer.extend_from_slice(&[13_u8, 10]);
+
+    // ensure start doesn't begin with "SSH-"
+    if buffer.starts_with(b"SSH-") {
+        buffer[0] = b'X';
+    }
+
+    buffer
+}
+
+fn randline_from(
+    mut rng: impl GetRandom,
+    line_length: LineLength,
+    alphabet: &Alphabet,
+    maxlen: usize,
+) -> Vec<u8> {
+    let len = line_length.sample(&mut rng, maxlen);
 
     let mut buffer = vec![0_u8; len];
 
     for l in buffer.iter_mut().take(len - 2) {
-        // ASCII 32 .. (including) ASCII 126
-        *l = rng.gen_range(32..=126);
+        *l = alphabet.sample(&mut rng);
     }
 
     buffer
@@ -66,14 +359,19 @@ fn randline_from(mut rng: impl GetRandom, maxlen: usize) -> Vec<u8> {
 
 #[cfg(test)]
 mod tests {
-    use std::ops::RangeInclusive;
+    use std::ops::{Range, RangeInclusive};
+    use std::sync::Mutex;
 
+    use mockall::predicate::eq;
     use mockall_double::double;
     use pretty_assertions::assert_eq;
 
     #[double]
     use crate::line::get_random::GetRandom;
-    use crate::line::randline_from;
+    use crate::line::shape::{Alphabet, LineLength};
+    use crate::line::{
+        Corpus, CorpusOrder, corpus_line, corpus_line_from, markov_line_from, randline_from,
+    };
 
     #[test]
     fn randline() {
@@ -92,7 +390,7 @@ mod tests {
             .return_const(b'a');
 
         let max_len = 50;
-        let randline = randline_from(mock_rng, max_len);
+        let randline = randline_from(mock_rng, LineLength::Uniform, &Alphabet::Printable, max_len);
 
         // then
         // did we get a line our length?
@@ -117,7 +415,7 @@ mod tests {
 
         // when
         let max_len = 50;
-        let randline = randline_from(ctx, max_len);
+        let randline = randline_from(ctx, LineLength::Uniform, &Alphabet::Printable, max_len);
 
         // then
         // did we get a line our length?
@@ -153,11 +451,123 @@ mod tests {
             .return_const(fake_randoms[3]);
 
         let max_len = 6;
-        let randline = randline_from(ctx, max_len);
+        let randline = randline_from(ctx, LineLength::Uniform, &Alphabet::Printable, max_len);
 
         assert_eq!(randline.len(), max_len);
 
         let xsh = *b"XSH-";
         assert_eq!(randline[..xsh.len()], xsh);
     }
+
+    #[test]
+    fn parses_corpus() {
+        assert_eq!(Corpus::parse(b"\n  \r\n\t\n"), None);
+
+        let corpus = Corpus::parse(b"first line\r\n\n  second line \n").unwrap();
+
+        assert_eq!(corpus.len(), 2);
+
+        let corpus = Corpus::parse(b"a\0b\rc\x1b\n\0\r\n").unwrap();
+
+        assert_eq!(corpus.lines, [b"abc"]);
+    }
+
+    #[test]
+    fn corpus_line_truncated() {
+        let corpus = Corpus::parse(b"short\nSSH-2.0-a much longer line\nlone\rCR\n").unwrap();
+
+        for (index, max_len, expected) in [
+            (0_usize, 50, b"short\r\n".as_slice()),
+            (0, 5, b"sho\r\n"),
+            (1, 10, b"XSH-2.0-\r\n"),
+            (2, 50, b"loneCR\r\n"),
+        ] {
+            let order = CorpusOrder {
+                remaining: Mutex::new(vec![index]),
+            };
+
+            assert_eq!(
+                corpus_line_from(GetRandom::new(), &corpus, &order, max_len),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn corpus_lines_shuffled() {
+        let corpus = Corpus::parse(b"first\nsecond\nthird\n").unwrap();
+        let order = CorpusOrder::default();
+
+        let mut ctx = GetRandom::new();
+
+        // swaps the last line with the first, then leaves the rest
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .with(eq(0..3))
+            .times(1)
+            .return_const(0_usize);
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .with(eq(0..2))
+            .times(1)
+            .return_const(1_usize);
+
+        assert_eq!(corpus_line_from(ctx, &corpus, &order, 50), b"first\r\n");
+
+        // shuffled once, until the order runs out
+        for expected in [b"second\r\n".as_slice(), b"third\r\n"] {
+            assert_eq!(
+                corpus_line_from(GetRandom::new(), &corpus, &order, 50),
+                expected
+            );
+        }
+
+        let mut lines = std::iter::repeat_with(|| corpus_line(&corpus, &order, 50))
+            .take(3)
+            .collect::<Vec<_>>();
+
+        lines.sort();
+
+        assert_eq!(
+            lines,
+            [b"first\r\n".as_slice(), b"second\r\n", b"third\r\n"],
+            "Every line before any repeats"
+        );
+    }
+
+    #[test]
+    fn markov_line_follows_corpus() {
+        let corpus = Corpus::parse(b"abcd\nabce\n").unwrap();
+
+        // every time, the first of what followed
+        let mut ctx = GetRandom::new();
+
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .returning(|x| x.start);
+
+        assert_eq!(
+            markov_line_from(ctx, &corpus.markov, 50),
+            b"abcd\r\n",
+            "Ends where the first line did"
+        );
+
+        // every time, the last of what followed
+        let mut ctx = GetRandom::new();
+
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .returning(|x| x.end - 1);
+
+        assert_eq!(markov_line_from(ctx, &corpus.markov, 50), b"abce\r\n");
+    }
+
+    #[test]
+    fn markov_line_truncated() {
+        let corpus = Corpus::parse(b"SSH-2.0-looks like a version\n").unwrap();
+
+        let mut ctx = GetRandom::new();
+
+        ctx.expect_gen_range::<usize, Range<usize>>()
+            .times(6)
+            .returning(|x| x.start);
+
+        assert_eq!(markov_line_from(ctx, &corpus.markov, 8), b"XSH-2.\r\n");
+    }
 }
@@ -0,0 +1,308 @@
+use std::str::FromStr;
+
+use color_eyre::eyre;
+use rand_distr::{Normal, Zipf};
+use serde::Deserialize;
+
+use crate::line::get_random::GetRandom;
+
+const ALPHANUMERIC: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const HEX: &[u8] = b"0123456789abcdef";
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How long the random lines are, CR LF included, between 3 and the maximum line length:
+/// `uniform`, `fixed`, `normal:<mean>:<standard deviation>` or `zipf:<exponent>`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
+pub enum LineLength {
+    /// Every length as likely.
+    #[default]
+    Uniform,
+    /// Always the maximum.
+    Fixed,
+    /// Rounded, and clamped to the lengths we can send.
+    Normal { mean: f64, std_dev: f64 },
+    /// Mostly short: the n-th shortest length is `n^exponent` times less likely than the shortest.
+    Zipf { exponent: f64 },
+}
+
+// the parameters are finite, that's checked when parsing
+impl Eq for LineLength {}
+
+impl LineLength {
+    /// Between 3 (a character and CR LF) and `maxlen`, which is at least 3.
+    pub fn sample<R: GetRandom>(self, rng: &mut R, maxlen: usize) -> usize {
+        match self {
+            // original did 3 + rand(s) % (maxlen - 2)
+            // so if rand(2) was 47, maxlen 50, the outcome is 3 + (47 % 48)
+            // we have a length of 50
+            // with a range we don't need to do - 2
+            LineLength::Uniform => rng.gen_range(3..=maxlen),
+            LineLength::Fixed => maxlen,
+            LineLength::Normal { mean, std_dev } => {
+                let normal = Normal::new(mean, std_dev).expect("Checked when parsing");
+
+                clamp_length(rng.sample(normal), maxlen)
+            },
+            LineLength::Zipf { exponent } => {
+                // rank 1 is the shortest line
+                let ranks = to_f64(maxlen - 2);
+                let zipf = Zipf::new(ranks, exponent).expect("Checked when parsing");
+
+                clamp_length(rng.sample(zipf) + 2.0, maxlen)
+            },
+        }
+    }
+}
+
+fn to_f64(length: usize) -> f64 {
+    // lengths fit, they're at most `MAX_LINE_LENGTH_LIMIT`
+    f64::from(u32::try_from(length).unwrap_or(u32::MAX))
+}
+
+#[expect(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    reason = "Rounded and clamped to the lengths first"
+)]
+fn clamp_length(length: f64, maxlen: usize) -> usize {
+    length.round().clamp(3.0, to_f64(maxlen)) as usize
+}
+
+impl FromStr for LineLength {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let parse = |number: &str| {
+            number
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite())
+                .ok_or_else(|| eyre::Report::msg(format!("Invalid line length {:?}", value)))
+        };
+
+        let line_length = match *value.split(':').collect::<Vec<_>>().as_slice() {
+            ["uniform"] => LineLength::Uniform,
+            ["fixed"] => LineLength::Fixed,
+            ["normal", mean, std_dev] => LineLength::Normal {
+                mean: parse(mean)?,
+                std_dev: parse(std_dev)?,
+            },
+            ["zipf", exponent] => LineLength::Zipf {
+                exponent: parse(exponent)?,
+            },
+            _ => {
+                return Err(eyre::Report::msg(format!(
+                    "Invalid line length {:?}, expected uniform, fixed, normal:<mean>:<standard deviation> or zipf:<exponent>",
+                    value
+                )));
+            },
+        };
+
+        // what's left are the ranges
+        let valid = match line_length {
+            LineLength::Uniform | LineLength::Fixed => true,
+            LineLength::Normal { mean, std_dev } => {
+                std_dev >= 0.0 && Normal::new(mean, std_dev).is_ok()
+            },
+            LineLength::Zipf { exponent } => Zipf::new(1.0, exponent).is_ok(),
+        };
+
+        if !valid {
+            return Err(eyre::Report::msg(format!(
+                "Invalid line length {:?}, out of range",
+                value
+            )));
+        }
+
+        Ok(line_length)
+    }
+}
+
+impl TryFrom<String> for LineLength {
+    type Error = eyre::Report;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl std::fmt::Display for LineLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            LineLength::Uniform => write!(f, "uniform"),
+            LineLength::Fixed => write!(f, "fixed"),
+            LineLength::Normal { mean, std_dev } => write!(f, "normal:{}:{}", mean, std_dev),
+            LineLength::Zipf { exponent } => write!(f, "zipf:{}", exponent),
+        }
+    }
+}
+
+/// What the random lines are made of: `printable`, `alphanumeric`, `hex`, `base64` or
+/// `custom:<characters>`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub enum Alphabet {
+    /// ASCII 32 to 126, space included.
+    #[default]
+    Printable,
+    Alphanumeric,
+    /// Lowercase.
+    Hex,
+    /// The standard alphabet (RFC 4648 4), without padding.
+    Base64,
+    /// ASCII without CR and LF. Repeated characters are more likely.
+    Custom(Vec<u8>),
+}
+
+impl Alphabet {
+    pub fn sample<R: GetRandom>(&self, rng: &mut R) -> u8 {
+        let characters: &[u8] = match *self {
+            Alphabet::Printable => return rng.gen_range(32..=126),
+            Alphabet::Alphanumeric => ALPHANUMERIC,
+            Alphabet::Hex => HEX,
+            Alphabet::Base64 => BASE64,
+            Alphabet::Custom(ref characters) => characters,
+        };
+
+        characters[rng.gen_range(0..characters.len())]
+    }
+}
+
+impl FromStr for Alphabet {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "printable" => Ok(Alphabet::Printable),
+            "alphanumeric" => Ok(Alphabet::Alphanumeric),
+            "hex" => Ok(Alphabet::Hex),
+            "base64" => Ok(Alphabet::Base64),
+            _ => {
+                let Some(characters) = value.strip_prefix("custom:") else {
+                    return Err(eyre::Report::msg(format!(
+                        "Invalid alphabet {:?}, expected printable, alphanumeric, hex, base64 or custom:<characters>",
+                        value
+                    )));
+                };
+
+                if characters.is_empty()
+                    || !characters.bytes().all(|character| {
+                        character.is_ascii() && character != b'\r' && character != b'\n'
+                    })
+                {
+                    return Err(eyre::Report::msg(format!(
+                        "Invalid alphabet {:?}, custom characters need to be ASCII, without CR and LF",
+                        value
+                    )));
+                }
+
+                Ok(Alphabet::Custom(characters.as_bytes().to_vec()))
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Alphabet {
+    type Error = eyre::Report;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl std::fmt::Display for Alphabet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Alphabet::Printable => write!(f, "printable"),
+            Alphabet::Alphanumeric => write!(f, "alphanumeric"),
+            Alphabet::Hex => write!(f, "hex"),
+            Alphabet::Base64 => write!(f, "base64"),
+            Alphabet::Custom(ref characters) => {
+                write!(f, "custom:{}", String::from_utf8_lossy(characters))
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::line::GenRange;
+    use crate::line::shape::{Alphabet, LineLength};
+    use crate::random::with_rng;
+
+    #[test]
+    fn parses_line_lengths() {
+        for (value, expected) in [
+            ("uniform", LineLength::Uniform),
+            ("fixed", LineLength::Fixed),
+            (
+                "normal:40:10",
+                LineLength::Normal {
+                    mean: 40.0,
+                    std_dev: 10.0,
+                },
+            ),
+            ("zipf:1.5", LineLength::Zipf { exponent: 1.5 }),
+        ] {
+            assert_eq!(value.parse::<LineLength>().unwrap(), expected);
+            assert_eq!(expected.to_string(), value, "Round trip");
+        }
+
+        for value in [
+            "",
+            "normal:40",
+            "normal:40:-1",
+            "zipf:NaN",
+            "zipf:-1",
+            "poisson",
+        ] {
+            #[expect(unused_must_use, reason = "Testing")]
+            value.parse::<LineLength>().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn parses_alphabets() {
+        assert_eq!("hex".parse::<Alphabet>().unwrap(), Alphabet::Hex);
+        assert_eq!(
+            "custom:ab-".parse::<Alphabet>().unwrap(),
+            Alphabet::Custom(b"ab-".to_vec())
+        );
+
+        for value in ["custom:", "custom:a\r\n", "custom:\u{e9}", "emoji"] {
+            #[expect(unused_must_use, reason = "Testing")]
+            value.parse::<Alphabet>().unwrap_err();
+        }
+    }
+
+    #[test]
+    fn lengths_stay_in_range() {
+        for line_length in [
+            LineLength::Uniform,
+            LineLength::Fixed,
+            LineLength::Normal {
+                mean: 10.0,
+                std_dev: 100.0,
+            },
+            LineLength::Zipf { exponent: 1.0 },
+        ] {
+            for maxlen in [3, 4, 50, 35000] {
+                for _ in 0..100 {
+                    let length = with_rng(|rng| line_length.sample(&mut GenRange { rng }, maxlen));
+
+                    assert!(
+                        (3..=maxlen).contains(&length),
+                        "{} out of range for {}",
+                        length,
+                        line_length
+                    );
+                }
+            }
+        }
+    }
+}
@@ -1,23 +1,35 @@
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
-use std::sync::Arc;
+use std::sync::{Arc, PoisonError};
+use std::task::Poll;
 
 use color_eyre::eyre;
+use time::OffsetDateTime;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::mpsc::UnboundedSender;
-use tokio::sync::{Semaphore, TryAcquireError};
-use tokio::time::Instant;
+use tokio::sync::{Semaphore, TryAcquireError, watch};
 use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
 use tracing::{Level, event};
 
 use crate::SIZE_IN_BYTES;
 use crate::client::Client;
-use crate::config::{BindFamily, Config};
+use crate::config::{BindFamily, Config, ListenerConfig};
 use crate::ffi_wrapper::set_receive_buffer_size;
+use crate::history::SharedHistory;
+use crate::protocol::Protocol;
+use crate::rules::{Connection, Treatment, find_rule};
+use crate::schedule::{self, Fallback};
 use crate::statistics::StatisticsMessage;
 
 struct Listener<'c> {
     config: &'c Config,
-    listener: TcpListener,
+    /// Forwarded connections, cancelled when we shut down.
+    forwards: TaskTracker,
+    cancellation_token: CancellationToken,
+    inner: TcpListener,
+    /// What we bound to, rules can match on it.
+    port: u16,
+    protocol: Protocol,
 }
 
 pub async fn listen_for_new_connections(
@@ -26,19 +38,38 @@ pub async fn listen_for_new_connections(
     client_sender: tokio::sync::mpsc::UnboundedSender<Client<TcpStream>>,
     semaphore: Arc<Semaphore>,
     statistics_sender: UnboundedSender<StatisticsMessage>,
+    history: SharedHistory,
+    active_receiver: watch::Receiver<bool>,
 ) {
     let _guard = cancellation_token.clone().drop_guard();
 
+    let mut listeners = Vec::new();
+
+    let forwards = TaskTracker::new();
+
     // listen forever, accept new clients
-    let listener = match Listener::bind(&config).await {
-        Ok(l) => l,
-        Err(error) => {
-            event!(Level::ERROR, ?error);
-            return;
-        },
-    };
+    for listener_config in config.listeners() {
+        match Listener::bind(
+            &config,
+            listener_config,
+            forwards.clone(),
+            cancellation_token.clone(),
+        )
+        .await
+        {
+            Ok(l) => {
+                event!(Level::INFO, listener = ?l.inner, protocol = %l.protocol, "Bound and listening!");
+
+                listeners.push(l);
+            },
+            Err(error) => {
+                event!(Level::ERROR, ?error, port = listener_config.port);
+                return;
+            },
+        }
+    }
 
-    event!(Level::INFO, listener = ?listener.listener, "Bound and listening!");
+    let mut turn = 0;
 
     loop {
         tokio::select! {
@@ -46,8 +77,8 @@ pub async fn listen_for_new_connections(
             () = cancellation_token.cancelled() => {
                 break;
             },
-            result = listener.accept(&client_sender, Arc::clone(&semaphore), &statistics_sender) => {
-                if let Err(error) = result {
+            (listener, accept) = accept_any(&listeners, &mut turn) => {
+                if let Err(error) = listener.accept(accept, &client_sender, &semaphore, &statistics_sender, &history, &active_receiver) {
                     event!(Level::ERROR, ?error);
 
                     // TODO properly log errors
@@ -56,38 +87,83 @@ pub async fn listen_for_new_connections(
             },
         }
     }
+
+    // on errors too, we're going down
+    cancellation_token.cancel();
+
+    forwards.close();
+    forwards.wait().await;
+}
+
+/// Accepts on whichever listener has a connection waiting. They take turns, so one busy listener
+/// can't starve the others.
+async fn accept_any<'l, 'c>(
+    listeners: &'l [Listener<'c>],
+    turn: &mut usize,
+) -> (
+    &'l Listener<'c>,
+    Result<(TcpStream, SocketAddr), std::io::Error>,
+) {
+    std::future::poll_fn(|cx| {
+        for (index, listener) in listeners
+            .iter()
+            .enumerate()
+            .cycle()
+            .skip(*turn)
+            .take(listeners.len())
+        {
+            if let Poll::Ready(accept) = listener.inner.poll_accept(cx) {
+                *turn = index + 1;
+
+                return Poll::Ready((listener, accept));
+            }
+        }
+
+        Poll::Pending
+    })
+    .await
 }
 
 impl<'c> Listener<'c> {
-    pub async fn bind(config: &'c Config) -> Result<Self, eyre::Report> {
+    pub async fn bind(
+        config: &'c Config,
+        listener_config: ListenerConfig,
+        forwards: TaskTracker,
+        cancellation_token: CancellationToken,
+    ) -> Result<Self, eyre::Report> {
+        let port = listener_config.port.get();
+
         let sa = match config.bind_family {
-            BindFamily::Ipv4 => {
-                SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, config.port.get()))
+            BindFamily::Ipv4 => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)),
+            BindFamily::Ipv6 | BindFamily::DualStack => {
+                SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, port, 0, 0))
             },
-            BindFamily::Ipv6 | BindFamily::DualStack => SocketAddr::V6(SocketAddrV6::new(
-                Ipv6Addr::UNSPECIFIED,
-                config.port.get(),
-                0,
-                0,
-            )),
         };
 
         // TODO BindFamily::Ipv6 is not respected. Dual stack / IPv6 only are
         // set by /proc/sys/net/ipv6/bindv6only
 
-        let listener = TcpListener::bind(sa).await?;
+        let tcp_listener = TcpListener::bind(sa).await?;
 
-        Ok(Self { config, listener })
+        Ok(Self {
+            config,
+            forwards,
+            cancellation_token,
+            inner: tcp_listener,
+            port,
+            protocol: listener_config.protocol,
+        })
     }
 
-    pub async fn accept(
+    pub fn accept(
         &self,
+        accept: Result<(TcpStream, SocketAddr), std::io::Error>,
         client_sender: &UnboundedSender<Client<TcpStream>>,
-        semaphore: Arc<Semaphore>,
+        semaphore: &Arc<Semaphore>,
         statistics_sender: &UnboundedSender<StatisticsMessage>,
+        history: &SharedHistory,
+        active_receiver: &watch::Receiver<bool>,
     ) -> Result<(), eyre::Report> {
-        let accept = self.listener.accept().await;
-
         {
             statistics_sender
                 .send(StatisticsMessage::NewClient)
@@ -95,6 +171,9 @@ impl<'c> Listener<'c> {
         }
 
         match accept {
+            Ok((socket, addr)) if !*active_receiver.borrow() => {
+                self.fall_back(socket, addr, semaphore, statistics_sender);
+            },
             Ok((socket, addr)) => {
                 // Set the smallest possible receive buffer. This reduces local
                 // resource usage and slows down the remote end.
@@ -105,39 +184,14 @@ impl<'c> Listener<'c> {
                         "Failed to set the tcp stream's receive buffer",
                     );
                 } else {
-                    // we do try_acquire because either we can add the client or we cannot
-                    // no in-between, no sense in waiting
-                    match Arc::clone(&semaphore).try_acquire_owned() {
-                        Ok(permit) => {
-                            let client = Client::new(
-                                socket,
-                                addr,
-                                Instant::now() + self.config.delay,
-                                permit,
-                            );
-
-                            // we have a permit, we can send it on the queue
-                            client_sender.send(client)?;
-
-                            let current_clients = usize::from(self.config.max_clients.get())
-                                - semaphore.available_permits();
-
-                            event!(
-                                Level::INFO,
-                                addr = ?addr,
-                                current_clients,
-                                max_clients = self.config.max_clients,
-                                "Accepted new client",
-                            );
-                        },
-                        Err(TryAcquireError::NoPermits) => {
-                            event!(Level::WARN, ?addr, "Queue full, not accepting new client");
-                        },
-                        Err(error @ TryAcquireError::Closed) => {
-                            return Err(eyre::Report::new(error)
-                                .wrap_err("Queue gone, not accepting new client"));
-                        },
-                    }
+                    self.admit(
+                        socket,
+                        addr,
+                        client_sender,
+                        semaphore,
+                        statistics_sender,
+                        history,
+                    )?;
                 }
             },
             Err(error) => match error.raw_os_error() {
@@ -174,4 +228,172 @@ impl<'c> Listener<'c> {
 
         Ok(())
     }
+
+    /// Outside of the schedule's windows, new clients aren't trapped.
+    fn fall_back(
+        &self,
+        socket: TcpStream,
+        addr: SocketAddr,
+        semaphore: &Arc<Semaphore>,
+        statistics_sender: &UnboundedSender<StatisticsMessage>,
+    ) {
+        let fallback = self
+            .config
+            .schedule
+            .as_ref()
+            .map_or(Fallback::Close, |schedule| schedule.fallback);
+
+        event!(Level::INFO, ?addr, %fallback, "Schedule closed, not trapping new client");
+
+        statistics_sender
+            .send(StatisticsMessage::FallbackClient)
+            .expect("Channel should always exist");
+
+        match fallback {
+            // dropping the socket closes it
+            Fallback::Close => drop(socket),
+            Fallback::Forward(target) => {
+                // forwarded connections take a slot too, so there's only so many
+                let Ok(permit) = Arc::clone(semaphore).try_acquire_owned() else {
+                    event!(Level::WARN, ?addr, "Queue full, not forwarding new client");
+
+                    return;
+                };
+
+                let forward =
+                    schedule::forward(socket, addr, target, self.cancellation_token.clone());
+
+                self.forwards.spawn(async move {
+                    forward.await;
+
+                    drop(permit);
+                });
+            },
+        }
+    }
+
+    /// Decides whether `addr` gets a slot, and how it is treated, based on what we remember of it
+    /// and the rules.
+    fn admit(
+        &self,
+        socket: TcpStream,
+        addr: SocketAddr,
+        client_sender: &UnboundedSender<Client<TcpStream>>,
+        semaphore: &Arc<Semaphore>,
+        statistics_sender: &UnboundedSender<StatisticsMessage>,
+        history: &SharedHistory,
+    ) -> Result<(), eyre::Report> {
+        let (previous_visits, active) = history
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(addr.ip())
+            .map_or((0, 0), |visitor| (visitor.visits(), visitor.active()));
+
+        let repeat_offender =
+            previous_visits + 1 >= u64::from(self.config.repeat_offender_visits.get());
+
+        let now = OffsetDateTime::now_utc();
+
+        let mut treatment = Treatment::defaults(self.config, self.protocol, repeat_offender);
+
+        let connection = Connection {
+            ip: addr.ip(),
+            local_port: self.port,
+            at: now,
+            repeat_offender,
+        };
+
+        if let Some(rule) = find_rule(&self.config.rules, &connection) {
+            treatment.apply(rule);
+        }
+
+        if !treatment.priority
+            && let Some(max_clients_per_ip) = self.config.max_clients_per_ip
+            && active >= usize::from(max_clients_per_ip.get())
+        {
+            event!(
+                Level::WARN,
+                ?addr,
+                active,
+                rule = treatment.rule.as_deref(),
+                "Too many clients from source, not accepting new client"
+            );
+
+            return Ok(());
+        }
+
+        // prioritized clients get to use the reserved slots
+        let reserved_slots = if treatment.priority {
+            0
+        } else {
+            usize::from(self.config.reserved_slots)
+        };
+
+        if semaphore.available_permits() <= reserved_slots {
+            event!(Level::WARN, ?addr, "Queue full, not accepting new client");
+
+            return Ok(());
+        }
+
+        // we do try_acquire because either we can add the client or we cannot
+        // no in-between, no sense in waiting
+        match Arc::clone(semaphore).try_acquire_owned() {
+            Ok(permit) => {
+                history
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .connect(addr.ip(), now);
+
+                let rule = treatment.rule.clone();
+                let protocol = treatment.protocol;
+
+                let client = Client::new(
+                    socket,
+                    addr,
+                    repeat_offender,
+                    treatment,
+                    permit,
+                    Arc::clone(history),
+                    self.config.seed,
+                );
+
+                let session_id = client.session_id();
+
+                // we have a permit, we can send it on the queue
+                client_sender.send(client)?;
+
+                if repeat_offender {
+                    statistics_sender
+                        .send(StatisticsMessage::RepeatOffender)
+                        .expect("Channel should always exist");
+                }
+
+                let current_clients =
+                    usize::from(self.config.max_clients.get()) - semaphore.available_permits();
+
+                event!(
+                    Level::INFO,
+                    addr = ?addr,
+                    session_id,
+                    current_clients,
+                    max_clients = self.config.max_clients,
+                    repeat_offender,
+                    previous_visits,
+                    rule,
+                    %protocol,
+                    "Accepted new client",
+                );
+            },
+            Err(TryAcquireError::NoPermits) => {
+                event!(Level::WARN, ?addr, "Queue full, not accepting new client");
+            },
+            Err(error @ TryAcquireError::Closed) => {
+                return Err(
+                    eyre::Report::new(error).wrap_err("Queue gone, not accepting new client")
+                );
+            },
+        }
+
+        Ok(())
+    }
 }
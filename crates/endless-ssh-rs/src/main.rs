@@ -1,27 +1,41 @@
+mod bandwidth;
 mod build_env;
+mod cidr;
 mod cli;
 mod client;
 mod client_queue;
 mod config;
+mod delay;
+mod disguise;
 mod ffi_wrapper;
 mod helpers;
+mod history;
 mod line;
 mod listener;
+mod offenders;
+mod protocol;
+mod random;
+mod rules;
+mod schedule;
 mod sender;
 mod signal_handlers;
 mod statistics;
+mod time_window;
 mod timeout;
 mod traits;
 mod utils;
 
 use std::env::{self, VarError};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use color_eyre::config::HookBuilder;
 use color_eyre::eyre;
 use dotenvy::dotenv;
+use time::OffsetDateTime;
 use tokio::net::TcpStream;
-use tokio::sync::Semaphore;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{Semaphore, watch};
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::TaskTracker;
@@ -30,13 +44,17 @@ use tracing_subscriber::layer::SubscriberExt as _;
 use tracing_subscriber::util::SubscriberInitExt as _;
 use tracing_subscriber::{EnvFilter, Layer as _};
 
+use crate::bandwidth::{SharedTokenBucket, TokenBucket};
 use crate::build_env::get_build_env;
 use crate::cli::parse_cli;
 use crate::client::Client;
 use crate::client_queue::process_clients;
 use crate::config::Config;
+use crate::history::{History, SharedHistory, persist_history};
 use crate::listener::listen_for_new_connections;
-use crate::statistics::{Statistics, statistics_sigusr1_handler};
+use crate::offenders::{OffenderMessage, Offenders};
+use crate::schedule::{OnClose, watch_schedule};
+use crate::statistics::{Statistics, StatisticsMessage, statistics_sigusr1_handler};
 use crate::utils::flatten_handle;
 
 #[global_allocator]
@@ -78,6 +96,134 @@ fn print_header() {
     );
 }
 
+async fn load_history(config: &Config) -> Result<SharedHistory, eyre::Report> {
+    let history = match config.history_file {
+        Some(ref history_file) => History::load(history_file, config.history_size).await?,
+        None => History::new(config.history_size),
+    };
+
+    Ok(Arc::new(Mutex::new(history)))
+}
+
+/// Whether we start out trapping new clients.
+fn is_active(config: &Config) -> bool {
+    config
+        .schedule
+        .as_ref()
+        .is_none_or(|schedule| schedule.is_active(OffsetDateTime::now_utc()))
+}
+
+fn create_bandwidth(config: &Config) -> Option<SharedTokenBucket> {
+    config.max_bandwidth.map(|max_bandwidth| {
+        Arc::new(Mutex::new(TokenBucket::new(
+            max_bandwidth,
+            config.bandwidth_burst.unwrap_or(max_bandwidth),
+            tokio::time::Instant::now(),
+        )))
+    })
+}
+
+/// Waits forever for either
+/// * SIGTERM
+/// * ctrl + c (SIGINT)
+/// * a message on the shutdown channel, sent either by the server task or
+///   another task when they complete (which means they failed).
+async fn wait_for_shutdown(cancellation_token: &CancellationToken) {
+    tokio::select! {
+        result = signal_handlers::wait_for_sigterm() => {
+            if let Err(error) = result {
+                event!(Level::ERROR, ?error, "Failed to register SIGERM handler, aborting");
+            } else {
+                // we completed because ...
+                event!(Level::WARN, "Sigterm detected, stopping all tasks");
+            }
+        },
+        result = signal_handlers::wait_for_sigint() => {
+            if let Err(error) = result {
+                event!(Level::ERROR, ?error, "Failed to register CTRL+C handler, aborting");
+            } else {
+                // we completed because ...
+                event!(Level::WARN, "CTRL+C detected, stopping all tasks");
+            }
+        },
+        () = cancellation_token.cancelled() => {
+            event!(Level::WARN, "Underlying task stopped, stopping all others tasks");
+        },
+    }
+}
+
+/// The tasks that need to outlive the clients, so they can account for (and write out) all of
+/// them.
+struct BackgroundTasks {
+    cancellation_token: CancellationToken,
+    statistics_join_handle: JoinHandle<Statistics>,
+    offenders_join_handle: Option<JoinHandle<Offenders>>,
+    history_join_handle: Option<JoinHandle<()>>,
+}
+
+impl BackgroundTasks {
+    fn start(
+        config: &Config,
+        history: &SharedHistory,
+        active: bool,
+        bandwidth: Option<SharedTokenBucket>,
+    ) -> (
+        Self,
+        UnboundedSender<StatisticsMessage>,
+        Option<UnboundedSender<OffenderMessage>>,
+    ) {
+        let cancellation_token = CancellationToken::new();
+
+        let history_join_handle = config.history_file.clone().map(|history_file| {
+            tokio::task::spawn(persist_history(
+                cancellation_token.clone(),
+                Arc::clone(history),
+                history_file,
+            ))
+        });
+
+        let (statistics_sender, statistics_join_handle) =
+            Statistics::new(cancellation_token.clone(), active, bandwidth);
+
+        let (offenders_sender, offenders_join_handle) = config
+            .offenders
+            .clone()
+            .map(|offenders_config| Offenders::new(offenders_config, cancellation_token.clone()))
+            .unzip();
+
+        let background_tasks = Self {
+            cancellation_token,
+            statistics_join_handle,
+            offenders_join_handle,
+            history_join_handle,
+        };
+
+        (background_tasks, statistics_sender, offenders_sender)
+    }
+
+    async fn stop(self) -> Result<(), eyre::Report> {
+        {
+            // cancel the statistics handler (and the offenders and history writers)
+            // now that the client processor is gone
+            self.cancellation_token.cancel();
+            // wait for abort and do a final abort
+            self.statistics_join_handle.await?.log_totals();
+        }
+
+        if let Some(offenders_join_handle) = self.offenders_join_handle {
+            // this does a final write
+            offenders_join_handle.await?;
+        }
+
+        if let Some(history_join_handle) = self.history_join_handle {
+            // this does a final write, now that all clients have been dropped
+            history_join_handle.await?;
+        }
+
+        Ok(())
+    }
+}
+
 /// Starts all the tasks, such as the web server, the key refresh, and
 /// ensures all tasks are gracefully shutdown in case of error, ctrl-c or `SIGTERM`.
 async fn start_tasks() -> Result<(), eyre::Report> {
@@ -90,10 +236,18 @@ async fn start_tasks() -> Result<(), eyre::Report> {
     // after which we'll gracefully terminate other services
     let cancellation_token = CancellationToken::new();
     let client_cancellation_token = CancellationToken::new();
-    let statistics_cancellation_token = CancellationToken::new();
 
-    let (statistics_sender, statistics_join_handle) =
-        Statistics::new(statistics_cancellation_token.clone());
+    let history = load_history(&config).await?;
+
+    let active = is_active(&config);
+
+    let bandwidth = create_bandwidth(&config);
+
+    let (background_tasks, statistics_sender, offenders_sender) =
+        BackgroundTasks::start(&config, &history, active, bandwidth.clone());
+
+    // whether we trap new clients, only changes when there is a schedule
+    let (active_sender, active_receiver) = watch::channel(active);
 
     // clients channel
     let (client_sender, client_receiver) =
@@ -111,6 +265,17 @@ async fn start_tasks() -> Result<(), eyre::Report> {
             client_sender.clone(),
             Arc::clone(&semaphore),
             statistics_sender.clone(),
+            Arc::clone(&history),
+            active_receiver.clone(),
+        ));
+    }
+
+    if let Some(schedule) = config.schedule.clone() {
+        tasks.spawn(watch_schedule(
+            cancellation_token.clone(),
+            schedule,
+            active_sender,
+            statistics_sender.clone(),
         ));
     }
 
@@ -118,11 +283,15 @@ async fn start_tasks() -> Result<(), eyre::Report> {
         // listen to new connection channel, convert into client, push to client channel
         tasks.spawn(process_clients(
             client_cancellation_token.clone(),
-            config.delay,
-            config.max_line_length,
-            client_sender.clone(),
             client_receiver,
             statistics_sender.clone(),
+            offenders_sender,
+            active_receiver,
+            config
+                .schedule
+                .as_ref()
+                .map_or(OnClose::Keep, |schedule| schedule.on_close),
+            bandwidth,
         ))
     };
 
@@ -135,32 +304,7 @@ async fn start_tasks() -> Result<(), eyre::Report> {
 
     tasks.close();
 
-    // now we wait forever for either
-    // * SIGTERM
-    // * ctrl + c (SIGINT)
-    // * a message on the shutdown channel, sent either by the server task or
-    // another task when they complete (which means they failed)
-    tokio::select! {
-        result = signal_handlers::wait_for_sigterm() => {
-            if let Err(error) = result {
-                event!(Level::ERROR, ?error, "Failed to register SIGERM handler, aborting");
-            } else {
-                // we completed because ...
-                event!(Level::WARN, "Sigterm detected, stopping all tasks");
-            }
-        },
-        result = signal_handlers::wait_for_sigint() => {
-            if let Err(error) = result {
-                event!(Level::ERROR, ?error, "Failed to register CTRL+C handler, aborting");
-            } else {
-                // we completed because ...
-                event!(Level::WARN, "CTRL+C detected, stopping all tasks");
-            }
-        },
-        () = cancellation_token.cancelled() => {
-            event!(Level::WARN, "Underlying task stopped, stopping all others tasks");
-        },
-    }
+    wait_for_shutdown(&cancellation_token).await;
 
     // backup, in case we forgot a dropguard somewhere
     cancellation_token.cancel();
@@ -177,12 +321,7 @@ async fn start_tasks() -> Result<(), eyre::Report> {
         );
     }
 
-    {
-        // cancel the statistics handler now that the client processor is gone
-        statistics_cancellation_token.cancel();
-        // wait for abort and do a final abort
-        statistics_join_handle.await?.log_totals();
-    }
+    background_tasks.stop().await?;
 
     // wait for the other tasks to shut down gracefully
     if timeout(StdDuration::from_secs(10), tasks.wait())
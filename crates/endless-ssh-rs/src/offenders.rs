@@ -0,0 +1,434 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::IpAddr;
+use std::path::Path;
+
+use color_eyre::eyre::{self, Context as _};
+use tokio::process::Command;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::config::{OffendersConfig, OffendersFormat};
+use crate::utils::fs::write_atomically;
+
+type StdDuration = std::time::Duration;
+
+/// Environment variable holding the path of the offenders file when running the command.
+const OFFENDERS_FILE_ENV: &str = "ENDLESS_SSH_OFFENDERS_FILE";
+
+pub enum OffenderMessage {
+    TimeSpent(IpAddr, StdDuration),
+}
+
+struct Tracked {
+    time_spent: StdDuration,
+    last_seen: Instant,
+}
+
+pub struct Offenders {
+    config: OffendersConfig,
+    tracked: BTreeMap<IpAddr, Tracked>,
+}
+
+impl Offenders {
+    pub fn new(
+        config: OffendersConfig,
+        cancellation_token: CancellationToken,
+    ) -> (UnboundedSender<OffenderMessage>, JoinHandle<Offenders>) {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<OffenderMessage>();
+
+        let task = tokio::task::spawn(async move {
+            let mut offenders = Self::from_config(config);
+
+            let mut write_interval = tokio::time::interval(offenders.config.interval);
+            write_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    () = cancellation_token.cancelled() => {
+                        break;
+                    },
+                    message = receiver.recv() => {
+                        match message {
+                            Some(OffenderMessage::TimeSpent(ip, duration)) => offenders.record(ip, duration, Instant::now()),
+                            None => {
+                                // the end
+                                break;
+                            },
+                        }
+                    },
+                    _ = write_interval.tick() => {
+                        offenders.write_and_report(Instant::now()).await;
+                    },
+                }
+            }
+
+            // one final write so that the file reflects what we knew at shutdown
+            offenders.write_and_report(Instant::now()).await;
+
+            offenders
+        });
+
+        (sender, task)
+    }
+
+    fn from_config(config: OffendersConfig) -> Self {
+        Self {
+            config,
+            tracked: BTreeMap::new(),
+        }
+    }
+
+    fn record(&mut self, ip: IpAddr, duration: StdDuration, now: Instant) {
+        // dual stack sockets give us IPv4 clients as `::ffff:a.b.c.d`
+        let tracked = self.tracked.entry(ip.to_canonical()).or_insert(Tracked {
+            time_spent: StdDuration::ZERO,
+            last_seen: now,
+        });
+
+        tracked.time_spent += duration;
+        tracked.last_seen = now;
+    }
+
+    /// Forgets every source we haven't seen in `entry_timeout`, offender or not.
+    fn prune(&mut self, now: Instant) {
+        let entry_timeout = self.config.entry_timeout;
+
+        self.tracked
+            .retain(|_, tracked| now.duration_since(tracked.last_seen) < entry_timeout);
+    }
+
+    /// Offenders with their remaining timeout, in whole seconds.
+    fn offenders(&self, now: Instant) -> impl Iterator<Item = (IpAddr, u64)> {
+        self.tracked.iter().filter_map(move |(&ip, tracked)| {
+            if tracked.time_spent <= self.config.threshold {
+                return None;
+            }
+
+            let remaining = self
+                .config
+                .entry_timeout
+                .saturating_sub(now.duration_since(tracked.last_seen));
+
+            // a timeout of 0 means 'use the default of the set' to both nft and ipset
+            Some((ip, remaining.as_secs().max(1)))
+        })
+    }
+
+    fn render(&self, now: Instant) -> String {
+        match self.config.format {
+            OffendersFormat::Nft => self.render_nft(now),
+            OffendersFormat::Ipset => self.render_ipset(now),
+        }
+    }
+
+    fn render_nft(&self, now: Instant) -> String {
+        let table = &self.config.nft_table;
+        let set_name = &self.config.set_name;
+
+        let mut script = String::new();
+
+        writeln!(script, "add table inet {}", table).unwrap();
+        writeln!(
+            script,
+            "add set inet {} {}_v4 {{ type ipv4_addr; flags timeout; }}",
+            table, set_name
+        )
+        .unwrap();
+        writeln!(
+            script,
+            "add set inet {} {}_v6 {{ type ipv6_addr; flags timeout; }}",
+            table, set_name
+        )
+        .unwrap();
+        writeln!(script, "flush set inet {} {}_v4", table, set_name).unwrap();
+        writeln!(script, "flush set inet {} {}_v6", table, set_name).unwrap();
+
+        for (ip, timeout) in self.offenders(now) {
+            writeln!(
+                script,
+                "add element inet {} {}_{} {{ {} timeout {}s }}",
+                table,
+                set_name,
+                family_suffix(ip),
+                ip,
+                timeout
+            )
+            .unwrap();
+        }
+
+        script
+    }
+
+    fn render_ipset(&self, now: Instant) -> String {
+        let set_name = &self.config.set_name;
+        let timeout = self.config.entry_timeout.as_secs();
+
+        let mut restore = String::new();
+
+        writeln!(
+            restore,
+            "create {}_v4 hash:ip family inet timeout {} -exist",
+            set_name, timeout
+        )
+        .unwrap();
+        writeln!(
+            restore,
+            "create {}_v6 hash:ip family inet6 timeout {} -exist",
+            set_name, timeout
+        )
+        .unwrap();
+        writeln!(restore, "flush {}_v4", set_name).unwrap();
+        writeln!(restore, "flush {}_v6", set_name).unwrap();
+
+        for (ip, timeout) in self.offenders(now) {
+            writeln!(
+                restore,
+                "add {}_{} {} timeout {} -exist",
+                set_name,
+                family_suffix(ip),
+                ip,
+                timeout
+            )
+            .unwrap();
+        }
+
+        restore
+    }
+
+    async fn write_and_report(&mut self, now: Instant) {
+        match self.write(now).await {
+            Ok(count) => {
+                event!(
+                    Level::DEBUG,
+                    path = %self.config.path.display(),
+                    offenders = count,
+                    "Offenders written"
+                );
+            },
+            Err(error) => {
+                event!(
+                    Level::ERROR,
+                    ?error,
+                    path = %self.config.path.display(),
+                    "Failed to write offenders"
+                );
+            },
+        }
+    }
+
+    /// Writes the offenders file and runs the configured command.
+    /// Returns the amount of offenders written.
+    async fn write(&mut self, now: Instant) -> Result<usize, eyre::Report> {
+        self.prune(now);
+
+        let contents = self.render(now);
+        let count = self.offenders(now).count();
+
+        let path = &self.config.path;
+
+        write_atomically(path, contents).await?;
+
+        if let Some(ref command) = self.config.command {
+            run_command(command, path, self.config.command_timeout).await?;
+        }
+
+        Ok(count)
+    }
+}
+
+fn family_suffix(ip: IpAddr) -> &'static str {
+    match ip {
+        IpAddr::V4(_) => "v4",
+        IpAddr::V6(_) => "v6",
+    }
+}
+
+/// A hanging command would hold up every later write, and shutting down.
+async fn run_command(command: &str, path: &Path, timeout: StdDuration) -> Result<(), eyre::Report> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env(OFFENDERS_FILE_ENV, path)
+        .kill_on_drop(true)
+        .status();
+
+    // on timeout, dropping the future kills the command
+    let status = tokio::time::timeout(timeout, status)
+        .await
+        .map_err(|_| {
+            eyre::Report::msg(format!(
+                "{:?} timed out after {}s",
+                command,
+                timeout.as_secs_f64()
+            ))
+        })?
+        .wrap_err_with(|| format!("Failed to run {:?}", command))?;
+
+    if !status.success() {
+        return Err(eyre::Report::msg(format!(
+            "{:?} failed with {}",
+            command, status
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use tokio::time::Instant;
+
+    use crate::config::{OffendersConfig, OffendersFormat};
+    use crate::offenders::Offenders;
+
+    fn offenders_factory(format: OffendersFormat, path: PathBuf) -> Offenders {
+        Offenders::from_config(OffendersConfig {
+            path,
+            format,
+            threshold: Duration::from_mins(1),
+            entry_timeout: Duration::from_hours(1),
+            interval: Duration::from_mins(1),
+            set_name: "offenders".to_owned(),
+            nft_table: "tarpit".to_owned(),
+            command: None,
+            command_timeout: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn nft_only_contains_sources_over_threshold() {
+        let mut offenders = offenders_factory(OffendersFormat::Nft, PathBuf::new());
+
+        let now = Instant::now();
+
+        let offender: IpAddr = "192.0.2.1".parse().unwrap();
+        let visitor: IpAddr = "192.0.2.2".parse().unwrap();
+
+        offenders.record(offender, Duration::from_secs(50), now);
+        offenders.record(offender, Duration::from_secs(50), now);
+        offenders.record(visitor, Duration::from_secs(50), now);
+
+        assert_eq!(
+            offenders.render(now + Duration::from_mins(10)),
+            "add table inet tarpit\n\
+             add set inet tarpit offenders_v4 { type ipv4_addr; flags timeout; }\n\
+             add set inet tarpit offenders_v6 { type ipv6_addr; flags timeout; }\n\
+             flush set inet tarpit offenders_v4\n\
+             flush set inet tarpit offenders_v6\n\
+             add element inet tarpit offenders_v4 { 192.0.2.1 timeout 3000s }\n"
+        );
+    }
+
+    #[test]
+    fn ipset_splits_families_and_unmaps_ipv4() {
+        let mut offenders = offenders_factory(OffendersFormat::Ipset, PathBuf::new());
+
+        let now = Instant::now();
+
+        offenders.record(
+            "::ffff:192.0.2.1".parse().unwrap(),
+            Duration::from_secs(61),
+            now,
+        );
+        offenders.record("2001:db8::1".parse().unwrap(), Duration::from_secs(61), now);
+
+        assert_eq!(
+            offenders.render(now),
+            "create offenders_v4 hash:ip family inet timeout 3600 -exist\n\
+             create offenders_v6 hash:ip family inet6 timeout 3600 -exist\n\
+             flush offenders_v4\n\
+             flush offenders_v6\n\
+             add offenders_v4 192.0.2.1 timeout 3600 -exist\n\
+             add offenders_v6 2001:db8::1 timeout 3600 -exist\n"
+        );
+    }
+
+    #[test]
+    fn expired_offenders_are_pruned() {
+        let mut offenders = offenders_factory(OffendersFormat::Ipset, PathBuf::new());
+
+        let now = Instant::now();
+
+        offenders.record("192.0.2.1".parse().unwrap(), Duration::from_mins(2), now);
+
+        offenders.prune(now + Duration::from_hours(1));
+
+        assert_eq!(offenders.offenders(now).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn writes_file_and_runs_command() {
+        let directory = tempfile::tempdir().unwrap();
+
+        let path = directory.path().join("offenders.nft");
+        let copy = directory.path().join("copy.nft");
+
+        let mut offenders = offenders_factory(OffendersFormat::Nft, path.clone());
+        offenders.config.command = Some(format!(
+            "cp \"$ENDLESS_SSH_OFFENDERS_FILE\" {}",
+            copy.display()
+        ));
+
+        let now = Instant::now();
+
+        offenders.record("192.0.2.1".parse().unwrap(), Duration::from_mins(2), now);
+
+        let count = offenders.write(now).await.unwrap();
+
+        assert_eq!(count, 1);
+
+        let written = std::fs::read_to_string(&path).unwrap();
+
+        assert!(
+            written.ends_with("add element inet tarpit offenders_v4 { 192.0.2.1 timeout 3600s }\n"),
+            "Unexpected contents: {}",
+            written
+        );
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), written);
+    }
+
+    #[tokio::test]
+    async fn failing_command_is_an_error() {
+        let directory = tempfile::tempdir().unwrap();
+
+        let mut offenders =
+            offenders_factory(OffendersFormat::Ipset, directory.path().join("offenders"));
+        offenders.config.command = Some("exit 3".to_owned());
+
+        #[expect(unused_must_use, reason = "Testing")]
+        offenders.write(Instant::now()).await.unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn hanging_command_times_out() {
+        let directory = tempfile::tempdir().unwrap();
+
+        let mut offenders =
+            offenders_factory(OffendersFormat::Ipset, directory.path().join("offenders"));
+        offenders.config.command = Some("sleep 10".to_owned());
+        offenders.config.command_timeout = Duration::from_millis(100);
+
+        let started = std::time::Instant::now();
+
+        let error = offenders.write(Instant::now()).await.unwrap_err();
+
+        assert!(
+            error.to_string().contains("timed out"),
+            "Unexpected error: {}",
+            error
+        );
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "Didn't wait for the command"
+        );
+    }
+}
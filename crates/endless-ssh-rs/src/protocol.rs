@@ -0,0 +1,388 @@
+pub mod auto;
+pub mod ftp;
+pub mod http;
+pub mod imap;
+pub mod memcached;
+pub mod mysql;
+pub mod pop3;
+pub mod postgres;
+pub mod proxy;
+pub mod rdp;
+pub mod redis;
+pub mod sip;
+pub mod smb;
+pub mod smtp;
+pub mod ssh;
+pub mod telnet;
+pub mod tls;
+pub mod vnc;
+pub mod wire;
+
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::pin::Pin;
+use std::task::Poll;
+
+use rand::RngExt;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::config::MAX_LINE_LENGTH_LIMIT;
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// What we pretend to be. Each client gets its own session, see [`TarpitProtocol`].
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    /// An endless SSH banner.
+    #[default]
+    Ssh,
+    /// An SSH key exchange that takes forever.
+    SshKex,
+    /// A greeting and replies that take forever, and a login that fails.
+    Ftp,
+    /// An endless list of response headers.
+    Http,
+    /// A slow greeting, and a login that never completes.
+    Imap,
+    /// Statistics and a version that never end.
+    Memcached,
+    /// A slow handshake, and an endless authentication.
+    Mysql,
+    /// A slow greeting, and a capability listing that never ends.
+    Pop3,
+    /// A slow request for the password, again and again.
+    Postgres,
+    /// An open proxy, whose connections go nowhere.
+    Proxy,
+    /// A connection that never gets past the security negotiation.
+    Rdp,
+    /// Replies that never end.
+    Redis,
+    /// A request that's tried forever, its response never ends.
+    Sip,
+    /// A response that never finishes.
+    Smb,
+    /// An endless greeting.
+    Smtp,
+    /// A login that takes forever, and fails.
+    Telnet,
+    /// A TLS handshake that never finishes.
+    Tls,
+    /// A slow handshake, and a failure that never ends.
+    Vnc,
+    /// Whichever of the above the client looks like it speaks.
+    Auto,
+}
+
+impl Protocol {
+    pub fn session(self) -> Box<dyn TarpitProtocol> {
+        match self {
+            Protocol::Ssh => Box::new(ssh::SshBanner::default()),
+            Protocol::SshKex => Box::new(ssh::SshKexStall::default()),
+            Protocol::Ftp => Box::new(ftp::Ftp::default()),
+            Protocol::Http => Box::new(http::Http::default()),
+            Protocol::Imap => Box::new(imap::Imap::default()),
+            Protocol::Memcached => Box::new(memcached::Memcached::default()),
+            Protocol::Mysql => Box::new(mysql::Mysql::default()),
+            Protocol::Pop3 => Box::new(pop3::Pop3::default()),
+            Protocol::Postgres => Box::new(postgres::Postgres::default()),
+            Protocol::Proxy => Box::new(proxy::Proxy::default()),
+            Protocol::Rdp => Box::new(rdp::Rdp::default()),
+            Protocol::Redis => Box::new(redis::Redis::default()),
+            Protocol::Sip => Box::new(sip::Sip::default()),
+            Protocol::Smb => Box::new(smb::Smb::default()),
+            Protocol::Smtp => Box::new(smtp::Smtp::default()),
+            Protocol::Telnet => Box::new(telnet::Telnet::default()),
+            Protocol::Tls => Box::new(tls::Tls::default()),
+            Protocol::Vnc => Box::new(vnc::Vnc::default()),
+            Protocol::Auto => Box::new(auto::Auto::default()),
+        }
+    }
+
+    /// The shortest maximum line length its lines fit in, some start with a prefix.
+    pub fn min_line_length(self) -> u16 {
+        match self {
+            Protocol::Ftp => ftp::MIN_LINE_LENGTH,
+            Protocol::Imap => imap::MIN_LINE_LENGTH,
+            Protocol::Pop3 => pop3::MIN_LINE_LENGTH,
+            Protocol::Smtp => smtp::MIN_LINE_LENGTH,
+            Protocol::Ssh
+            | Protocol::SshKex
+            | Protocol::Http
+            | Protocol::Memcached
+            | Protocol::Mysql
+            | Protocol::Postgres
+            | Protocol::Proxy
+            | Protocol::Rdp
+            | Protocol::Redis
+            | Protocol::Sip
+            | Protocol::Smb
+            | Protocol::Telnet
+            | Protocol::Tls
+            | Protocol::Vnc
+            | Protocol::Auto => 3,
+        }
+    }
+
+    /// The longest maximum line length its clients take.
+    pub fn max_line_length(self) -> u16 {
+        match self {
+            // auto falls back to the banner
+            Protocol::Ssh | Protocol::SshKex | Protocol::Auto => ssh::MAX_LINE_LENGTH,
+            Protocol::Ftp
+            | Protocol::Http
+            | Protocol::Imap
+            | Protocol::Memcached
+            | Protocol::Mysql
+            | Protocol::Pop3
+            | Protocol::Postgres
+            | Protocol::Proxy
+            | Protocol::Rdp
+            | Protocol::Redis
+            | Protocol::Sip
+            | Protocol::Smb
+            | Protocol::Smtp
+            | Protocol::Telnet
+            | Protocol::Tls
+            | Protocol::Vnc => MAX_LINE_LENGTH_LIMIT,
+        }
+    }
+}
+
+impl std::fmt::Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Protocol::Ssh => write!(f, "ssh"),
+            Protocol::SshKex => write!(f, "ssh-kex"),
+            Protocol::Ftp => write!(f, "ftp"),
+            Protocol::Http => write!(f, "http"),
+            Protocol::Imap => write!(f, "imap"),
+            Protocol::Memcached => write!(f, "memcached"),
+            Protocol::Mysql => write!(f, "mysql"),
+            Protocol::Pop3 => write!(f, "pop3"),
+            Protocol::Postgres => write!(f, "postgres"),
+            Protocol::Proxy => write!(f, "proxy"),
+            Protocol::Rdp => write!(f, "rdp"),
+            Protocol::Redis => write!(f, "redis"),
+            Protocol::Sip => write!(f, "sip"),
+            Protocol::Smb => write!(f, "smb"),
+            Protocol::Smtp => write!(f, "smtp"),
+            Protocol::Telnet => write!(f, "telnet"),
+            Protocol::Tls => write!(f, "tls"),
+            Protocol::Vnc => write!(f, "vnc"),
+            Protocol::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+/// Something the peer told us that's worth logging, like its client version or the credentials
+/// it tried.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Capture {
+    pub kind: &'static str,
+    pub value: String,
+}
+
+impl Capture {
+    pub fn new(kind: &'static str, value: &[u8]) -> Self {
+        Self {
+            kind,
+            value: String::from_utf8_lossy(value).into_owned(),
+        }
+    }
+}
+
+/// Splits what the peer sends into lines, without the line ending.
+#[derive(Debug, Default)]
+pub struct Lines {
+    line: Vec<u8>,
+}
+
+impl Lines {
+    /// Longer lines are cut off, we only log them anyway.
+    const MAX_LINE_LENGTH: usize = 1024;
+
+    /// Returns the lines that are complete now.
+    pub fn push(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+
+        for &byte in data {
+            if byte == b'\n' {
+                let mut line = std::mem::take(&mut self.line);
+
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+
+                lines.push(line);
+            } else if self.line.len() < Self::MAX_LINE_LENGTH {
+                self.line.push(byte);
+            } else {
+                // cut off
+            }
+        }
+
+        lines
+    }
+}
+
+/// Appends what fits of `data` to `buffer`, up to `max_length` in total. Dropping all of it would
+/// lose our place in what the peer sends.
+pub fn keep_up_to(buffer: &mut Vec<u8>, data: &[u8], max_length: usize) {
+    let available = max_length.saturating_sub(buffer.len());
+
+    buffer.extend_from_slice(data.get(..available).unwrap_or(data));
+}
+
+/// Commands waiting for their reply, the rest is only captured.
+pub const MAX_QUEUED_COMMANDS: usize = 16;
+
+/// The first word of a command line.
+pub fn command_name(command: &[u8]) -> &[u8] {
+    command
+        .split(u8::is_ascii_whitespace)
+        .next()
+        .unwrap_or_default()
+}
+
+/// Everything after the first word, trimmed.
+pub fn command_argument(command: &[u8]) -> &[u8] {
+    command
+        .iter()
+        .position(u8::is_ascii_whitespace)
+        .and_then(|space| command.get(space..))
+        .unwrap_or_default()
+        .trim_ascii()
+}
+
+/// What a binary protocol sends a byte at a time. Random bytes, for the parts that never end, come
+/// after the rest.
+#[derive(Debug, Default)]
+pub struct Drip {
+    bytes: VecDeque<u8>,
+    random: u32,
+}
+
+impl Drip {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend(bytes);
+    }
+
+    pub fn push_random(&mut self, count: u32) {
+        self.random += count;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty() && self.random == 0
+    }
+
+    /// The next byte as a tick, empty when there's nothing left.
+    pub fn pop(&mut self) -> Vec<u8> {
+        if let Some(byte) = self.bytes.pop_front() {
+            vec![byte]
+        } else if self.random > 0 {
+            self.random -= 1;
+
+            vec![with_rng(RngExt::random)]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// A single client's session. The client processor calls `setup` once after accepting the
+/// connection, then alternates between `receive` (when the peer sent anything) and `tick`, waiting
+/// the treatment's delay before each tick. After each tick, it takes the tick's captures.
+pub trait TarpitProtocol: Send + Sync {
+    /// Sent right after the connection was accepted.
+    fn setup(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Sent after each delay, keep it short.
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8>;
+
+    /// Handles what the peer sent, which might change what the next tick sends.
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let _: &[u8] = data;
+
+        Vec::new()
+    }
+
+    /// What the last tick captured, most only capture what the peer sent.
+    fn take_captures(&mut self) -> Vec<Capture> {
+        Vec::new()
+    }
+}
+
+/// Reads whatever the peer sent, without waiting for more. `Ok(0)` means nothing (or the end).
+pub async fn receive<T: AsyncRead + std::marker::Unpin>(
+    source: &mut T,
+    buffer: &mut [u8],
+) -> Result<usize, std::io::Error> {
+    std::future::poll_fn(|cx| {
+        let mut read_buffer = ReadBuf::new(buffer);
+
+        match Pin::new(&mut *source).poll_read(cx, &mut read_buffer) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(read_buffer.filled().len())),
+            Poll::Ready(Err(error)) if error.kind() == ErrorKind::WouldBlock => Poll::Ready(Ok(0)),
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+            // nothing there, we're not going to wait
+            Poll::Pending => Poll::Ready(Ok(0)),
+        }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use tokio::io::AsyncWriteExt as _;
+
+    use crate::protocol::{Lines, command_argument, command_name, receive};
+
+    #[test]
+    fn splits_lines() {
+        let mut lines = Lines::default();
+
+        assert_eq!(lines.push(b"EHLO example.com\r"), Vec::<Vec<u8>>::new());
+        assert_eq!(
+            lines.push(b"\nQUIT\nRS"),
+            [b"EHLO example.com".to_vec(), b"QUIT".to_vec()]
+        );
+        assert_eq!(lines.push(b"ET\r\n"), [b"RSET".to_vec()]);
+    }
+
+    #[test]
+    fn splits_commands() {
+        for (command, name, argument) in [
+            (
+                b"USER  admin ".as_slice(),
+                b"USER".as_slice(),
+                b"admin".as_slice(),
+            ),
+            (b"PASS pass word", b"PASS", b"pass word"),
+            (b"QUIT", b"QUIT", b""),
+            (b"", b"", b""),
+        ] {
+            assert_eq!(command_name(command), name);
+            assert_eq!(command_argument(command), argument);
+        }
+    }
+
+    #[tokio::test]
+    async fn receive_does_not_wait() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+
+        let mut buffer = [0_u8; 16];
+
+        assert_eq!(receive(&mut server, &mut buffer).await.unwrap(), 0);
+
+        client.write_all(b"hello").await.unwrap();
+
+        assert_eq!(receive(&mut server, &mut buffer).await.unwrap(), 5);
+        assert_eq!(&buffer[..5], b"hello");
+    }
+}
@@ -0,0 +1,256 @@
+use crate::protocol::{Capture, Protocol, TarpitProtocol, keep_up_to};
+use crate::rules::Treatment;
+
+const HTTP_METHODS: &[&[u8]] = &[
+    b"GET ",
+    b"HEAD ",
+    b"POST ",
+    b"PUT ",
+    b"DELETE ",
+    b"CONNECT ",
+    b"OPTIONS ",
+    b"TRACE ",
+    b"PATCH ",
+    b"PRI ",
+];
+
+/// What we keep while we can't tell yet. More than enough, as all we need is a prefix.
+const MAX_SNIFFED_LENGTH: usize = 1024;
+
+/// Listens until the first tick, so the treatment's delay is the timeout. Whatever the client sent
+/// by then (or as soon as it's enough to tell) decides what we pretend to be. Clients that stay
+/// silent, or that we don't recognize, get the SSH banner.
+#[derive(Default)]
+pub struct Auto {
+    sniffed: Vec<u8>,
+    session: Option<Box<dyn TarpitProtocol>>,
+    /// The session's setup, sent with the first tick.
+    pending: Vec<u8>,
+    /// From switching on a tick, until they're taken.
+    captures: Vec<Capture>,
+}
+
+/// What the client's first bytes look like.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Detected {
+    Tls,
+    Http,
+    Ssh,
+    Rdp,
+    Smb,
+    Unknown,
+    Silent,
+}
+
+impl Detected {
+    fn protocol(self) -> Protocol {
+        match self {
+            Detected::Tls => Protocol::Tls,
+            Detected::Http => Protocol::Http,
+            Detected::Rdp => Protocol::Rdp,
+            Detected::Smb => Protocol::Smb,
+            Detected::Ssh | Detected::Unknown | Detected::Silent => Protocol::Ssh,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Detected::Tls => "tls",
+            Detected::Http => "http",
+            Detected::Ssh => "ssh",
+            Detected::Rdp => "rdp",
+            Detected::Smb => "smb",
+            Detected::Unknown => "unknown",
+            Detected::Silent => "silent",
+        }
+    }
+}
+
+/// `None` when we need more to tell.
+fn detect(data: &[u8]) -> Option<Detected> {
+    let prefix_of = |signature: &[u8]| signature.starts_with(data);
+
+    if data.starts_with(b"SSH-") {
+        return Some(Detected::Ssh);
+    }
+
+    if HTTP_METHODS.iter().any(|method| data.starts_with(method)) {
+        return Some(Detected::Http);
+    }
+
+    if data.is_empty() || prefix_of(b"SSH-") || HTTP_METHODS.iter().any(|method| prefix_of(method))
+    {
+        return None;
+    }
+
+    match *data {
+        // handshake record, RFC 8446 5.1
+        [0x16, 0x03, ..] => Some(Detected::Tls),
+        // TPKT, RFC 1006 6
+        [0x03, 0x00, ..] => Some(Detected::Rdp),
+        // direct TCP (RFC 1002 4.3.1), with an SMB 1 or 2 header, or a NetBIOS session request
+        [0x00, _, _, _, 0xfe | 0xff, b'S', b'M', b'B', ..] | [0x81, ..] => Some(Detected::Smb),
+        [0x16 | 0x03] => None,
+        // the length, and maybe part of the header
+        [0x00, ref rest @ ..]
+            if rest.get(3..).is_none_or(|header| {
+                b"\xfeSMB".starts_with(header) || b"\xffSMB".starts_with(header)
+            }) =>
+        {
+            None
+        },
+        _ => Some(Detected::Unknown),
+    }
+}
+
+impl Auto {
+    fn switch(&mut self, detected: Detected) -> Vec<Capture> {
+        let mut session = detected.protocol().session();
+
+        self.pending = session.setup();
+
+        let mut captures = vec![Capture::new("detected", detected.name().as_bytes())];
+
+        captures.extend(session.receive(&std::mem::take(&mut self.sniffed)));
+
+        self.session = Some(session);
+
+        captures
+    }
+}
+
+impl TarpitProtocol for Auto {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        let session = if let Some(session) = self.session.as_mut() {
+            session
+        } else {
+            // time's up
+            let detected = detect(&self.sniffed).unwrap_or(if self.sniffed.is_empty() {
+                Detected::Silent
+            } else {
+                Detected::Unknown
+            });
+
+            self.captures = self.switch(detected);
+
+            self.session.as_mut().expect("Just switched")
+        };
+
+        let tick = session.tick(treatment);
+
+        self.captures.extend(session.take_captures());
+
+        if self.pending.is_empty() {
+            tick
+        } else {
+            [std::mem::take(&mut self.pending), tick].concat()
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if let Some(session) = self.session.as_mut() {
+            return session.receive(data);
+        }
+
+        keep_up_to(&mut self.sniffed, data, MAX_SNIFFED_LENGTH);
+
+        match detect(&self.sniffed) {
+            Some(detected) => self.switch(detected),
+            None => Vec::new(),
+        }
+    }
+
+    fn take_captures(&mut self) -> Vec<Capture> {
+        std::mem::take(&mut self.captures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::auto::{Auto, Detected, detect};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn detects() {
+        for (data, detected) in [
+            (b"".as_slice(), None),
+            (b"SS", None),
+            (b"SSH-2.0-Go\r\n", Some(Detected::Ssh)),
+            (b"GE", None),
+            (b"GET / HTTP/1.1\r\n", Some(Detected::Http)),
+            (b"PRI * HTTP/2.0\r\n", Some(Detected::Http)),
+            (b"\x16", None),
+            (b"\x16\x03\x01\x02\x00\x01", Some(Detected::Tls)),
+            (b"\x03\x00\x00\x2b\x26\xe0", Some(Detected::Rdp)),
+            (b"\0\0\0", None),
+            (b"\0\0\0\x85\xffS", None),
+            (b"\0\0\0\x85\xffSMBr", Some(Detected::Smb)),
+            (b"\0\0\0\x85\xfeSMB", Some(Detected::Smb)),
+            (b"\0\0\0\x85\xfeXYZ", Some(Detected::Unknown)),
+            (b"\x81\0\0\x44", Some(Detected::Smb)),
+            (b"HELO", Some(Detected::Unknown)),
+        ] {
+            assert_eq!(detect(data), detected, "{data:?}");
+        }
+    }
+
+    #[test]
+    fn switches() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Auto, false);
+
+        let mut auto = Auto::default();
+
+        assert_eq!(auto.setup(), b"");
+        assert_eq!(auto.receive(b"GE"), [], "Can't tell yet");
+        assert_eq!(
+            auto.receive(b"T / HTTP/1.1\r\nHost: example.com\r\n"),
+            [
+                Capture::new("detected", b"http"),
+                Capture::new("request_line", b"GET / HTTP/1.1"),
+                Capture::new("host", b"example.com"),
+            ]
+        );
+        assert_eq!(auto.tick(&treatment), b"HTTP/1.1 200 OK\r\n");
+    }
+
+    #[test]
+    fn falls_back_to_ssh() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Auto, false);
+
+        let mut auto = Auto::default();
+
+        assert_eq!(auto.receive(b"\x03"), [], "Can't tell yet");
+
+        let line = auto.tick(&treatment);
+
+        assert!(line.ends_with(b"\r\n"), "Banner line");
+        assert!(!line.starts_with(b"SSH-"), "Not the identification");
+
+        assert_eq!(auto.take_captures(), [Capture::new("detected", b"unknown")]);
+        assert_eq!(
+            auto.receive(b"SSH-2.0-Go\r\n"),
+            [Capture::new("garbage", b"\x03SSH-2.0-Go")]
+        );
+    }
+
+    #[test]
+    fn captures_silent_clients_on_the_tick() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Auto, false);
+
+        let mut auto = Auto::default();
+
+        assert_eq!(auto.take_captures(), [], "Nothing yet");
+
+        auto.tick(&treatment);
+
+        assert_eq!(auto.take_captures(), [Capture::new("detected", b"silent")]);
+
+        auto.tick(&treatment);
+
+        assert_eq!(auto.take_captures(), [], "Only once");
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+
+use crate::protocol::{
+    Capture, Lines, MAX_QUEUED_COMMANDS, TarpitProtocol, command_argument, command_name,
+};
+use crate::rules::Treatment;
+
+/// A reply code, `-`, a character and CR LF.
+pub const MIN_LINE_LENGTH: u16 = 7;
+
+/// Continuation lines before the actual reply, one per tick.
+const GREETING_LINES: u32 = 30;
+const PASSWORD_LINES: u32 = 10;
+const FAILURE_LINES: u32 = 30;
+const REJECTION_LINES: u32 = 10;
+
+/// A multi-line greeting (RFC 959 4.2), that ends eventually. After that, every command gets a
+/// multi-line reply just as slow. `USER` asks for the password, which is always wrong.
+#[derive(Debug, Default)]
+pub struct Ftp {
+    lines: Lines,
+    commands: VecDeque<Vec<u8>>,
+    reply: Option<Reply>,
+}
+
+#[derive(Debug)]
+struct Reply {
+    code: &'static str,
+    continuations: u32,
+    text: &'static str,
+}
+
+impl Reply {
+    /// Like vsftpd says it.
+    fn to(command: &[u8]) -> Self {
+        let (code, continuations, text) = match command_name(command) {
+            name if name.eq_ignore_ascii_case(b"USER") => {
+                ("331", PASSWORD_LINES, "Please specify the password.")
+            },
+            name if name.eq_ignore_ascii_case(b"PASS") => {
+                ("530", FAILURE_LINES, "Login incorrect.")
+            },
+            _ => ("530", REJECTION_LINES, "Please login with USER and PASS."),
+        };
+
+        Self {
+            code,
+            continuations,
+            text,
+        }
+    }
+}
+
+impl TarpitProtocol for Ftp {
+    fn setup(&mut self) -> Vec<u8> {
+        self.reply = Some(Reply {
+            code: "220",
+            continuations: GREETING_LINES,
+            text: "(vsFTPd 3.0.5)",
+        });
+
+        // tell them who we are right away, so they stay
+        b"220-FTP server ready.\r\n".to_vec()
+    }
+
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        if self.reply.is_none() {
+            self.reply = self.commands.pop_front().map(|command| Reply::to(&command));
+        }
+
+        let Some(reply) = self.reply.as_mut() else {
+            return Vec::new();
+        };
+
+        if reply.continuations == 0 {
+            let line = format!("{} {}\r\n", reply.code, reply.text).into_bytes();
+
+            self.reply = None;
+
+            return line;
+        }
+
+        reply.continuations -= 1;
+
+        let max_length = NonZeroUsize::from(treatment.max_line_length).get();
+
+        // at least `MIN_LINE_LENGTH`, that's checked when parsing
+        let text = treatment.line(max_length.saturating_sub(reply.code.len() + 1).max(3));
+
+        [format!("{}-", reply.code).as_bytes(), &text].concat()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for line in self.lines.push(data) {
+            let name = command_name(&line);
+
+            if name.is_empty() {
+                continue;
+            }
+
+            if name.eq_ignore_ascii_case(b"USER") {
+                captures.push(Capture::new("username", command_argument(&line)));
+            } else if name.eq_ignore_ascii_case(b"PASS") {
+                captures.push(Capture::new("password", command_argument(&line)));
+            } else {
+                captures.push(Capture::new("command", &line));
+            }
+
+            if self.commands.len() < MAX_QUEUED_COMMANDS {
+                self.commands.push_back(line);
+            }
+        }
+
+        captures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::ftp::{
+        FAILURE_LINES, Ftp, GREETING_LINES, MIN_LINE_LENGTH, PASSWORD_LINES,
+    };
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn greets_and_rejects_slowly() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Ftp, false);
+
+        let mut ftp = Ftp::default();
+
+        assert_eq!(ftp.setup(), b"220-FTP server ready.\r\n");
+
+        // impatient
+        assert_eq!(
+            ftp.receive(b"USER anonymous\r\nPASS  guest@example.com \r\n"),
+            [
+                Capture::new("username", b"anonymous"),
+                Capture::new("password", b"guest@example.com"),
+            ]
+        );
+
+        for (code, continuations, reply) in [
+            ("220", GREETING_LINES, "220 (vsFTPd 3.0.5)\r\n"),
+            (
+                "331",
+                PASSWORD_LINES,
+                "331 Please specify the password.\r\n",
+            ),
+            ("530", FAILURE_LINES, "530 Login incorrect.\r\n"),
+        ] {
+            for _ in 0..continuations {
+                let line = ftp.tick(&treatment);
+
+                assert!(
+                    line.starts_with(format!("{code}-").as_bytes()),
+                    "Continuation"
+                );
+                assert!(line.ends_with(b"\r\n"), "CR LF");
+                assert!(
+                    line.len() <= usize::from(treatment.max_line_length.get()),
+                    "Max line length"
+                );
+            }
+
+            assert_eq!(ftp.tick(&treatment), reply.as_bytes());
+        }
+
+        assert_eq!(ftp.tick(&treatment), b"", "Waits for the next command");
+    }
+
+    #[test]
+    fn shortest_lines_fit() {
+        let mut treatment = Treatment::defaults(&Config::default(), Protocol::Ftp, false);
+
+        treatment.max_line_length = NonZeroU16::new(MIN_LINE_LENGTH).unwrap();
+
+        let mut ftp = Ftp::default();
+
+        ftp.setup();
+
+        for _ in 0..GREETING_LINES {
+            assert_eq!(ftp.tick(&treatment).len(), 7, "Fits exactly");
+        }
+    }
+}
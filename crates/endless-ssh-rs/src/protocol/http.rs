@@ -0,0 +1,177 @@
+use rand::RngExt as _;
+
+use crate::protocol::{Capture, Lines, TarpitProtocol};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+const STATUS_LINE: &[u8] = b"HTTP/1.1 200 OK\r\n";
+
+/// `X-a: b\r\n`.
+const MIN_HEADER_LENGTH: usize = 8;
+
+const HEADER_NAME_ALPHABET: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-";
+
+/// Waits for the request line, answers `200 OK` and then sends headers, forever.
+#[derive(Debug, Default)]
+pub struct Http {
+    lines: Lines,
+    state: State,
+    status_sent: bool,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+enum State {
+    /// Nothing to answer yet.
+    #[default]
+    Waiting,
+    /// Got the request line, reading the headers.
+    Requested,
+    /// Got all headers.
+    Read,
+}
+
+impl TarpitProtocol for Http {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        if self.state == State::Waiting {
+            return Vec::new();
+        }
+
+        if !self.status_sent {
+            self.status_sent = true;
+
+            return STATUS_LINE.to_vec();
+        }
+
+        random_header(treatment.max_line_length.get().into())
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for line in self.lines.push(data) {
+            match self.state {
+                State::Waiting => {
+                    // clients may send empty lines before the request line, RFC 9112 2.2
+                    if !line.is_empty() {
+                        captures.push(Capture::new("request_line", &line));
+
+                        self.state = State::Requested;
+                    }
+                },
+                State::Requested => {
+                    if line.is_empty() {
+                        self.state = State::Read;
+                    } else if let Some((name, value)) = split_header(&line) {
+                        if name.eq_ignore_ascii_case(b"host") {
+                            captures.push(Capture::new("host", value));
+                        } else if name.eq_ignore_ascii_case(b"user-agent") {
+                            captures.push(Capture::new("user_agent", value));
+                        } else {
+                            // not interesting
+                        }
+                    } else {
+                        // not a header
+                    }
+                },
+                // the response never ends, so there's no next request
+                State::Read => break,
+            }
+        }
+
+        captures
+    }
+}
+
+/// `Name: value`, with the value trimmed.
+pub fn split_header(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let colon = line.iter().position(|&byte| byte == b':')?;
+
+    let (name, value) = line.split_at(colon);
+
+    Some((name, value.get(1..)?.trim_ascii()))
+}
+
+/// Like `X-Abc: random`, at most `max_length` long (including CR LF), but at least 8.
+pub fn random_header(max_length: usize) -> Vec<u8> {
+    with_rng(|rng| {
+        let length = rng.random_range(MIN_HEADER_LENGTH..=max_length.max(MIN_HEADER_LENGTH));
+
+        // what's left after `X-`, `: ` and CR LF, at least 2
+        let available = length - 6;
+        let name_length = rng.random_range(1..available);
+        let value_length = available - name_length;
+
+        let mut header = Vec::with_capacity(length);
+
+        header.extend_from_slice(b"X-");
+
+        for _ in 0..name_length {
+            header.push(HEADER_NAME_ALPHABET[rng.random_range(0..HEADER_NAME_ALPHABET.len())]);
+        }
+
+        header.extend_from_slice(b": ");
+
+        for _ in 0..value_length {
+            // visible ASCII, so no leading whitespace
+            header.push(rng.random_range(33..=126));
+        }
+
+        header.extend_from_slice(b"\r\n");
+
+        header
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::http::{Http, random_header, split_header};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn captures_request_and_answers_forever() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Http, false);
+
+        let mut http = Http::default();
+
+        assert_eq!(http.tick(&treatment), b"", "Waits for the request");
+
+        assert_eq!(
+            http.receive(b"\r\nGET /wp-login.php HTTP/1.1\r\nHost: example.com\r\nuser-agent:  curl/8.5.0 \r\nAccept: */*\r\n"),
+            [
+                Capture::new("request_line", b"GET /wp-login.php HTTP/1.1"),
+                Capture::new("host", b"example.com"),
+                Capture::new("user_agent", b"curl/8.5.0"),
+            ]
+        );
+
+        assert_eq!(http.tick(&treatment), b"HTTP/1.1 200 OK\r\n");
+
+        for _ in 0..100 {
+            let header = http.tick(&treatment);
+
+            assert!(header.starts_with(b"X-"), "Header name");
+            assert!(header.ends_with(b"\r\n"), "CR LF");
+            assert!(
+                header.len() <= usize::from(treatment.max_line_length.get()),
+                "Max line length"
+            );
+            assert!(split_header(&header).is_some(), "Header");
+        }
+
+        assert_eq!(
+            http.receive(b"\r\nGET / HTTP/1.1\r\nHost: other\r\n"),
+            [],
+            "No second request"
+        );
+    }
+
+    #[test]
+    fn headers_have_a_minimum_length() {
+        assert_eq!(random_header(3).len(), 8);
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+
+use crate::protocol::{Capture, Drip, Lines, MAX_QUEUED_COMMANDS, TarpitProtocol};
+use crate::rules::Treatment;
+
+const CAPABILITIES: &str = "IMAP4rev1 SASL-IR LOGIN-REFERRALS ID ENABLE IDLE LITERAL+ AUTH=PLAIN";
+
+const UNTAGGED_OK: &[u8] = b"* OK ";
+
+/// `* OK `, a character and CR LF.
+pub const MIN_LINE_LENGTH: u16 = 8;
+
+/// Drips the greeting, and every reply, a byte per tick. `LOGIN` never completes: it gets untagged
+/// `* OK` lines (RFC 9051 7.1) forever, instead of its tagged `NO`.
+#[derive(Debug, Default)]
+pub struct Imap {
+    lines: Lines,
+    commands: VecDeque<Vec<u8>>,
+    sending: Drip,
+    logging_in: bool,
+}
+
+impl Imap {
+    fn reply(&mut self, command: &[u8]) {
+        let mut words = command.splitn(3, u8::is_ascii_whitespace);
+        let tag = String::from_utf8_lossy(words.next().unwrap_or_default());
+        let name = words.next().unwrap_or_default();
+
+        let reply = if name.eq_ignore_ascii_case(b"CAPABILITY") {
+            format!(
+                "* CAPABILITY {CAPABILITIES}\r\n{tag} OK Pre-login capabilities listed, post-login capabilities have more.\r\n"
+            )
+        } else if name.eq_ignore_ascii_case(b"LOGIN") {
+            self.logging_in = true;
+
+            return;
+        } else if name.eq_ignore_ascii_case(b"LOGOUT") {
+            format!("* BYE Logging out\r\n{tag} OK Logout completed.\r\n")
+        } else {
+            format!("{tag} BAD Error in IMAP command received by server.\r\n")
+        };
+
+        self.sending.push(reply.as_bytes());
+    }
+
+    fn untagged_line(treatment: &Treatment) -> Vec<u8> {
+        let max_length = NonZeroUsize::from(treatment.max_line_length).get();
+
+        // at least `MIN_LINE_LENGTH`, that's checked when parsing
+        let mut text = treatment.line(max_length.saturating_sub(UNTAGGED_OK.len()).max(3));
+
+        // that would be the start of a response code
+        if text.starts_with(b"[") {
+            text[0] = b'X';
+        }
+
+        [UNTAGGED_OK, &text].concat()
+    }
+}
+
+/// The arguments of `LOGIN`, as atoms or quoted strings (RFC 9051 4.3).
+fn astrings(mut arguments: &[u8]) -> Vec<Vec<u8>> {
+    let mut astrings = Vec::new();
+
+    loop {
+        arguments = arguments.trim_ascii_start();
+
+        match *arguments {
+            [] => return astrings,
+            [b'"', ref quoted @ ..] => {
+                let mut astring = Vec::new();
+                let mut bytes = quoted.iter();
+
+                while let Some(&byte) = bytes.next() {
+                    match byte {
+                        b'"' => break,
+                        b'\\' => astring.extend(bytes.next()),
+                        _ => astring.push(byte),
+                    }
+                }
+
+                astrings.push(astring);
+                arguments = bytes.as_slice();
+            },
+            _ => {
+                let end = arguments
+                    .iter()
+                    .position(u8::is_ascii_whitespace)
+                    .unwrap_or(arguments.len());
+
+                let (atom, rest) = arguments.split_at(end);
+
+                astrings.push(atom.to_vec());
+                arguments = rest;
+            },
+        }
+    }
+}
+
+impl TarpitProtocol for Imap {
+    fn setup(&mut self) -> Vec<u8> {
+        self.sending.push(
+            format!("* OK [CAPABILITY {CAPABILITIES}] Dovecot (Ubuntu) ready.\r\n").as_bytes(),
+        );
+
+        Vec::new()
+    }
+
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        if self.sending.is_empty() && !self.logging_in {
+            if let Some(command) = self.commands.pop_front() {
+                self.reply(&command);
+            }
+        }
+
+        if self.sending.is_empty() && self.logging_in {
+            return Imap::untagged_line(treatment);
+        }
+
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for line in self.lines.push(data) {
+            let mut words = line.splitn(3, u8::is_ascii_whitespace);
+            let _tag = words.next().unwrap_or_default();
+            let name = words.next().unwrap_or_default();
+
+            if name.is_empty() {
+                continue;
+            }
+
+            if name.eq_ignore_ascii_case(b"LOGIN") {
+                let arguments = astrings(words.next().unwrap_or_default());
+
+                let mut arguments = arguments.iter();
+
+                captures.extend(
+                    arguments
+                        .next()
+                        .map(|username| Capture::new("username", username)),
+                );
+                captures.extend(
+                    arguments
+                        .next()
+                        .map(|password| Capture::new("password", password)),
+                );
+            } else {
+                captures.push(Capture::new("command", &line));
+            }
+
+            if self.commands.len() < MAX_QUEUED_COMMANDS {
+                self.commands.push_back(line);
+            }
+        }
+
+        captures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::imap::{Imap, MIN_LINE_LENGTH, astrings};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn logs_in_forever() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Imap, false);
+
+        let mut imap = Imap::default();
+
+        assert_eq!(imap.setup(), b"");
+        assert_eq!(
+            imap.receive(b"a1 CAPABILITY\r\na2 LOGIN \"admin\" \"pass word\"\r\n"),
+            [
+                Capture::new("command", b"a1 CAPABILITY"),
+                Capture::new("username", b"admin"),
+                Capture::new("password", b"pass word"),
+            ]
+        );
+
+        let mut replies = Vec::new();
+
+        while !replies.ends_with(
+            b"a1 OK Pre-login capabilities listed, post-login capabilities have more.\r\n",
+        ) {
+            let tick = imap.tick(&treatment);
+
+            assert_eq!(tick.len(), 1, "A byte per tick");
+
+            replies.extend(tick);
+        }
+
+        assert!(replies.starts_with(b"* OK [CAPABILITY "), "Greeting");
+
+        for _ in 0..100 {
+            let line = imap.tick(&treatment);
+
+            assert!(line.starts_with(b"* OK "), "Untagged");
+            assert!(!line.starts_with(b"* OK ["), "No response code");
+            assert!(line.ends_with(b"\r\n"), "CR LF");
+            assert!(
+                line.len() <= usize::from(treatment.max_line_length.get()),
+                "Max line length"
+            );
+        }
+    }
+
+    #[test]
+    fn shortest_lines_fit() {
+        let mut treatment = Treatment::defaults(&Config::default(), Protocol::Imap, false);
+
+        treatment.max_line_length = NonZeroU16::new(MIN_LINE_LENGTH).unwrap();
+
+        for _ in 0..100 {
+            assert_eq!(Imap::untagged_line(&treatment).len(), 8, "Fits exactly");
+        }
+    }
+
+    #[test]
+    fn parses_astrings() {
+        assert_eq!(
+            astrings(b"user  \"p\\\"a\\\\ss\" extra"),
+            [b"user".to_vec(), b"p\"a\\ss".to_vec(), b"extra".to_vec()]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::num::NonZeroUsize;
+
+use rand::RngExt as _;
+
+use crate::protocol::{Capture, Lines, TarpitProtocol};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// Statistics, with random values.
+const STATS: &[&str] = &[
+    "pid",
+    "uptime",
+    "time",
+    "curr_connections",
+    "total_connections",
+    "cmd_get",
+    "cmd_set",
+    "get_hits",
+    "get_misses",
+    "curr_items",
+    "total_items",
+    "evictions",
+    "bytes",
+    "threads",
+];
+
+/// Answers `stats` with statistics that never get to `END`, and `version` with a version that never
+/// ends. Everything else is captured and left waiting.
+#[derive(Debug, Default)]
+pub struct Memcached {
+    lines: Lines,
+    reply: Option<Reply>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Reply {
+    Stats,
+    VersionHeader,
+    Version,
+}
+
+impl TarpitProtocol for Memcached {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        match self.reply {
+            None => Vec::new(),
+            Some(Reply::Stats) => stat_line(NonZeroUsize::from(treatment.max_line_length).get()),
+            Some(Reply::VersionHeader) => {
+                self.reply = Some(Reply::Version);
+
+                b"VERSION 1.6.".to_vec()
+            },
+            Some(Reply::Version) => vec![with_rng(|rng| rng.random_range(b'0'..=b'9'))],
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for line in self.lines.push(data) {
+            let Some(name) = line.split(u8::is_ascii_whitespace).next() else {
+                continue;
+            };
+
+            if name.is_empty() {
+                continue;
+            }
+
+            captures.push(Capture::new("command", &line));
+
+            // the rest wait for the first reply
+            if self.reply.is_none() {
+                if name.eq_ignore_ascii_case(b"stats") {
+                    self.reply = Some(Reply::Stats);
+                } else if name.eq_ignore_ascii_case(b"version") {
+                    self.reply = Some(Reply::VersionHeader);
+                } else {
+                    // no answer at all
+                }
+            }
+        }
+
+        captures
+    }
+}
+
+/// Like `STAT curr_items 12`, CR LF included.
+fn stat_line(max_length: usize) -> Vec<u8> {
+    with_rng(|rng| {
+        let name = STATS[rng.random_range(0..STATS.len())];
+
+        let digits = rng.random_range(1..=max_length.saturating_sub(name.len() + 8).max(1));
+
+        let mut line = format!("STAT {name} ").into_bytes();
+
+        line.extend(std::iter::repeat_with(|| rng.random_range(b'0'..=b'9')).take(digits));
+        line.extend_from_slice(b"\r\n");
+
+        line
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::memcached::Memcached;
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn stats_never_end() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Memcached, false);
+
+        let mut memcached = Memcached::default();
+
+        assert_eq!(memcached.tick(&treatment), b"", "Waits for a command");
+        assert_eq!(
+            memcached.receive(b"stats\r\nversion\r\n"),
+            [
+                Capture::new("command", b"stats"),
+                Capture::new("command", b"version"),
+            ]
+        );
+
+        for _ in 0..100 {
+            let line = memcached.tick(&treatment);
+
+            assert!(line.starts_with(b"STAT "), "Statistic");
+            assert!(line.ends_with(b"\r\n"), "CR LF");
+            assert!(
+                line.len() <= usize::from(treatment.max_line_length.get()),
+                "Max line length"
+            );
+        }
+    }
+
+    #[test]
+    fn version_never_ends() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Memcached, false);
+
+        let mut memcached = Memcached::default();
+
+        assert_eq!(
+            memcached.receive(b"version\n"),
+            [Capture::new("command", b"version")]
+        );
+        assert_eq!(memcached.tick(&treatment), b"VERSION 1.6.");
+
+        for _ in 0..100 {
+            assert!(
+                memcached.tick(&treatment)[0].is_ascii_digit(),
+                "More version"
+            );
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use rand::RngExt as _;
+
+use crate::protocol::wire::{
+    put_u16_le, put_u24_le, put_u32_le, read_bytes, read_c_string, read_u8, read_u16_le,
+    read_u24_le, read_u32_le,
+};
+use crate::protocol::{Capture, Drip, TarpitProtocol, keep_up_to};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// What a recent Ubuntu says.
+const SERVER_VERSION: &[u8] = b"8.0.36-0ubuntu0.22.04.1";
+
+/// Everything up to `CLIENT_REMEMBER_OPTIONS`, except `CLIENT_SSL`, so the login isn't encrypted.
+const CAPABILITIES: u32 = 0x003f_f7ff;
+
+const CLIENT_CONNECT_WITH_DB: u32 = 0x0000_0008;
+const CLIENT_PROTOCOL_41: u32 = 0x0000_0200;
+const CLIENT_SECURE_CONNECTION: u32 = 0x0000_8000;
+const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x0020_0000;
+
+/// `utf8mb4_0900_ai_ci`.
+const CHARACTER_SET: u8 = 255;
+
+/// `SERVER_STATUS_AUTOCOMMIT`.
+const STATUS: u16 = 2;
+
+const AUTH_PLUGIN: &[u8] = b"caching_sha2_password";
+
+/// Before the first login, the most we keep of what the client sends.
+const MAX_LOGIN_LENGTH: usize = 4096;
+
+/// Our answer to the login, `AuthMoreData` that the client waits for in full.
+const STALL_LENGTH: u32 = 0xffff;
+const AUTH_MORE_DATA: u8 = 1;
+
+/// Drips the initial handshake, reads the login and then drips an endless authentication packet.
+#[derive(Debug, Default)]
+pub struct Mysql {
+    received: Vec<u8>,
+    greeted: bool,
+    logged_in: bool,
+    sending: Drip,
+}
+
+impl TarpitProtocol for Mysql {
+    fn tick(&mut self, _treatment: &Treatment) -> Vec<u8> {
+        if !self.greeted {
+            self.greeted = true;
+            self.sending.push(&packet(0, &greeting()));
+        }
+
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.logged_in {
+            // they're waiting for us now
+            return Vec::new();
+        }
+
+        // with the header
+        keep_up_to(&mut self.received, data, MAX_LOGIN_LENGTH + 4);
+
+        let mut received = self.received.as_slice();
+
+        let (Some(length), Some(_sequence_id)) =
+            (read_u24_le(&mut received), read_u8(&mut received))
+        else {
+            return Vec::new();
+        };
+
+        let length = usize::try_from(length).unwrap_or(usize::MAX);
+
+        let captures = if length > MAX_LOGIN_LENGTH {
+            vec![Capture::new("garbage", &self.received)]
+        } else if let Some(payload) = read_bytes(&mut received, length) {
+            parse_login(payload).unwrap_or_else(|| vec![Capture::new("garbage", &self.received)])
+        } else {
+            // wait for the rest
+            return Vec::new();
+        };
+
+        self.logged_in = true;
+        self.received = Vec::new();
+
+        let mut header = Vec::new();
+        put_u24_le(&mut header, STALL_LENGTH);
+        // the login was 1
+        header.push(2);
+        header.push(AUTH_MORE_DATA);
+
+        self.sending.push(&header);
+        self.sending.push_random(STALL_LENGTH - 1);
+
+        captures
+    }
+}
+
+/// Initial Handshake Packet, protocol version 10.
+fn greeting() -> Vec<u8> {
+    with_rng(|rng| {
+        // printable, like the server does
+        let mut scramble = [0_u8; 20];
+        scramble.fill_with(|| rng.random_range(33..=126));
+
+        let (scramble_1, scramble_2) = scramble.split_at(8);
+
+        let [capabilities_1, capabilities_2] = [CAPABILITIES & 0xffff, CAPABILITIES >> 16]
+            .map(|half| u16::try_from(half).expect("Half of a u32 fits in a u16"));
+
+        let mut greeting = vec![10];
+
+        greeting.extend_from_slice(SERVER_VERSION);
+        greeting.push(0);
+        // connection ID
+        put_u32_le(&mut greeting, rng.random_range(1000..100_000));
+        greeting.extend_from_slice(scramble_1);
+        greeting.push(0);
+        put_u16_le(&mut greeting, capabilities_1);
+        greeting.push(CHARACTER_SET);
+        put_u16_le(&mut greeting, STATUS);
+        put_u16_le(&mut greeting, capabilities_2);
+        // length of the scramble, with its NUL
+        greeting.push(21);
+        greeting.extend_from_slice(&[0; 10]);
+        greeting.extend_from_slice(scramble_2);
+        greeting.push(0);
+        greeting.extend_from_slice(AUTH_PLUGIN);
+        greeting.push(0);
+
+        greeting
+    })
+}
+
+fn packet(sequence_id: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(4 + payload.len());
+
+    put_u24_le(
+        &mut packet,
+        u32::try_from(payload.len()).expect("Our packets are small"),
+    );
+    packet.push(sequence_id);
+    packet.extend_from_slice(payload);
+
+    packet
+}
+
+/// Length-encoded integer, only the lengths we'd accept anyway.
+fn read_length(data: &mut &[u8]) -> Option<usize> {
+    match read_u8(data)? {
+        length @ 0..=0xfa => Some(usize::from(length)),
+        0xfc => read_u16_le(data).map(usize::from),
+        0xfd => usize::try_from(read_u24_le(data)?).ok(),
+        _ => None,
+    }
+}
+
+/// Handshake Response Packet, protocol 4.1, the user and the database it wants.
+fn parse_login(mut payload: &[u8]) -> Option<Vec<Capture>> {
+    let capabilities = read_u32_le(&mut payload)?;
+
+    if capabilities & CLIENT_PROTOCOL_41 == 0 {
+        return None;
+    }
+
+    // max packet size, character set and filler
+    let _ignored: &[u8] = read_bytes(&mut payload, 4 + 1 + 23)?;
+
+    let mut captures = vec![Capture::new("username", read_c_string(&mut payload)?)];
+
+    let auth_response_length = if capabilities & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+        read_length(&mut payload)
+    } else if capabilities & CLIENT_SECURE_CONNECTION != 0 {
+        read_u8(&mut payload).map(usize::from)
+    } else {
+        read_c_string(&mut payload).map(<[u8]>::len)
+    };
+
+    // the hash's no use to us
+    let _auth_response: &[u8] = read_bytes(&mut payload, auth_response_length?)?;
+
+    if capabilities & CLIENT_CONNECT_WITH_DB != 0 {
+        if let Some(database) = read_c_string(&mut payload) {
+            captures.push(Capture::new("database", database));
+        }
+    }
+
+    Some(captures)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::mysql::{
+        AUTH_MORE_DATA, CAPABILITIES, CLIENT_PROTOCOL_41, MAX_LOGIN_LENGTH, Mysql, packet,
+    };
+    use crate::protocol::wire::{put_u24_le, put_u32_le, read_u24_le};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn greets_slowly_and_captures_login() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Mysql, false);
+
+        let mut mysql = Mysql::default();
+
+        let mut greeting = Vec::new();
+
+        for _ in 0..4 {
+            greeting.extend(mysql.tick(&treatment));
+        }
+
+        let length = usize::try_from(read_u24_le(&mut greeting.as_slice()).unwrap()).unwrap();
+
+        while greeting.len() < 4 + length {
+            let tick = mysql.tick(&treatment);
+
+            assert_eq!(tick.len(), 1, "One byte per tick");
+
+            greeting.extend(tick);
+        }
+
+        assert_eq!(mysql.tick(&treatment), b"", "Waits for the login");
+        assert_eq!(greeting[3..5], [0, 10], "Protocol version 10");
+        assert!(greeting.ends_with(b"caching_sha2_password\0"), "Plugin");
+
+        let mut login = Vec::new();
+
+        put_u32_le(&mut login, CAPABILITIES);
+        login.extend_from_slice(&[0; 28]);
+        login.extend_from_slice(b"root\0");
+        login.extend_from_slice(&[3, 1, 2, 3]);
+        login.extend_from_slice(b"wordpress\0caching_sha2_password\0");
+
+        let login = packet(1, &login);
+        let (first, second) = login.split_at(10);
+
+        assert_eq!(mysql.receive(first), [], "Waits for the whole packet");
+        assert_eq!(
+            mysql.receive(second),
+            [
+                Capture::new("username", b"root"),
+                Capture::new("database", b"wordpress"),
+            ]
+        );
+
+        let stall = (0..5)
+            .flat_map(|_| mysql.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(stall, [0xff, 0xff, 0, 2, AUTH_MORE_DATA]);
+        assert_eq!(mysql.tick(&treatment).len(), 1, "And on it goes");
+    }
+
+    #[test]
+    fn captures_oversized_packets_as_garbage() {
+        let mut mysql = Mysql::default();
+
+        let mut login = Vec::new();
+        put_u24_le(&mut login, u32::try_from(MAX_LOGIN_LENGTH + 1).unwrap());
+        login.push(1);
+
+        assert_eq!(mysql.receive(&login), [Capture::new("garbage", &login)]);
+        assert_eq!(mysql.receive(b"more"), [], "Waits for us now");
+    }
+
+    #[test]
+    fn captures_logins_without_protocol_41_as_garbage() {
+        let mut mysql = Mysql::default();
+
+        let mut login = Vec::new();
+
+        put_u32_le(&mut login, CAPABILITIES & !CLIENT_PROTOCOL_41);
+        login.extend_from_slice(&[0; 28]);
+        login.extend_from_slice(b"root\0");
+
+        let login = packet(1, &login);
+
+        assert_eq!(mysql.receive(&login), [Capture::new("garbage", &login)]);
+    }
+}
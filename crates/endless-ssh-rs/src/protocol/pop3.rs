@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+
+use crate::protocol::{
+    Capture, Drip, Lines, MAX_QUEUED_COMMANDS, TarpitProtocol, command_argument, command_name,
+};
+use crate::rules::Treatment;
+
+const GREETING: &[u8] = b"+OK Dovecot (Ubuntu) ready.\r\n";
+
+/// A stuffed `.`, a character and CR LF.
+pub const MIN_LINE_LENGTH: u16 = 4;
+
+/// Drips the greeting, and every reply, a byte per tick. The password is always wrong, and the
+/// capability listing (RFC 2449 5) never gets to its terminating `.`.
+#[derive(Debug, Default)]
+pub struct Pop3 {
+    lines: Lines,
+    commands: VecDeque<Vec<u8>>,
+    sending: Drip,
+    listing: bool,
+}
+
+impl Pop3 {
+    fn reply(&mut self, command: &[u8]) {
+        let name = command_name(command);
+
+        let reply: &[u8] = if name.eq_ignore_ascii_case(b"CAPA") {
+            self.listing = true;
+
+            b"+OK\r\n"
+        } else if name.eq_ignore_ascii_case(b"USER") {
+            b"+OK\r\n"
+        } else if name.eq_ignore_ascii_case(b"PASS") {
+            b"-ERR [AUTH] Authentication failed.\r\n"
+        } else {
+            b"-ERR Unknown command.\r\n"
+        };
+
+        self.sending.push(reply);
+    }
+
+    /// A line of the listing, byte-stuffed (RFC 1939 3) so it's never the terminator.
+    fn listing_line(treatment: &Treatment) -> Vec<u8> {
+        let max_length = NonZeroUsize::from(treatment.max_line_length).get();
+
+        // at least `MIN_LINE_LENGTH`, that's checked when parsing
+        let line = treatment.line(max_length.saturating_sub(1).max(3));
+
+        if line.starts_with(b".") {
+            [b".", line.as_slice()].concat()
+        } else {
+            line
+        }
+    }
+}
+
+impl TarpitProtocol for Pop3 {
+    fn setup(&mut self) -> Vec<u8> {
+        self.sending.push(GREETING);
+
+        Vec::new()
+    }
+
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        if self.sending.is_empty() {
+            if self.listing {
+                return Pop3::listing_line(treatment);
+            }
+
+            if let Some(command) = self.commands.pop_front() {
+                self.reply(&command);
+            }
+        }
+
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for line in self.lines.push(data) {
+            let name = command_name(&line);
+
+            if name.is_empty() {
+                continue;
+            }
+
+            if name.eq_ignore_ascii_case(b"USER") {
+                captures.push(Capture::new("username", command_argument(&line)));
+            } else if name.eq_ignore_ascii_case(b"PASS") {
+                captures.push(Capture::new("password", command_argument(&line)));
+            } else {
+                captures.push(Capture::new("command", &line));
+            }
+
+            if self.commands.len() < MAX_QUEUED_COMMANDS {
+                self.commands.push_back(line);
+            }
+        }
+
+        captures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use crate::config::Config;
+    use crate::line::shape::Alphabet;
+    use crate::protocol::pop3::{GREETING, MIN_LINE_LENGTH, Pop3};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn fails_slowly_and_lists_forever() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Pop3, false);
+
+        let mut pop3 = Pop3::default();
+
+        assert_eq!(pop3.setup(), b"");
+        assert_eq!(
+            pop3.receive(b"USER admin\r\nPASS hunter2\r\nCAPA\r\n"),
+            [
+                Capture::new("username", b"admin"),
+                Capture::new("password", b"hunter2"),
+                Capture::new("command", b"CAPA"),
+            ]
+        );
+
+        for reply in [
+            GREETING,
+            b"+OK\r\n",
+            b"-ERR [AUTH] Authentication failed.\r\n",
+            b"+OK\r\n",
+        ] {
+            let ticks = std::iter::repeat_with(|| pop3.tick(&treatment))
+                .take(reply.len())
+                .collect::<Vec<_>>();
+
+            assert!(ticks.iter().all(|tick| tick.len() == 1), "A byte per tick");
+            assert_eq!(ticks.concat(), reply);
+        }
+
+        for _ in 0..100 {
+            let line = pop3.tick(&treatment);
+
+            assert!(line.ends_with(b"\r\n"), "CR LF");
+            assert_ne!(line, b".\r\n", "Never the end");
+            assert!(
+                line.len() <= usize::from(treatment.max_line_length.get()),
+                "Max line length"
+            );
+        }
+    }
+
+    #[test]
+    fn shortest_lines_fit() {
+        let mut treatment = Treatment::defaults(&Config::default(), Protocol::Pop3, false);
+
+        treatment.max_line_length = NonZeroU16::new(MIN_LINE_LENGTH).unwrap();
+        // every line gets stuffed
+        treatment.alphabet = Alphabet::Custom(b".".to_vec());
+
+        for _ in 0..100 {
+            let line = Pop3::listing_line(&treatment);
+
+            assert!(line.starts_with(b".."), "Stuffed");
+            assert!(line.len() <= 4, "Fits");
+        }
+    }
+}
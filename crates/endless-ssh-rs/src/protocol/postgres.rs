@@ -0,0 +1,268 @@
+use crate::protocol::wire::{put_u32, read_c_string, read_u8, read_u32};
+use crate::protocol::{Capture, Drip, TarpitProtocol, keep_up_to};
+use crate::rules::Treatment;
+
+/// The startup packet's codes, protocol 3.0 and the encryption requests.
+const PROTOCOL_VERSION: u32 = 196_608;
+const SSL_REQUEST: u32 = 80_877_103;
+const GSS_ENCRYPTION_REQUEST: u32 = 80_877_104;
+
+/// The most we keep of a single message.
+const MAX_MESSAGE_LENGTH: usize = 10_000;
+
+/// `AuthenticationCleartextPassword`, so we get to see the password.
+const PASSWORD_REQUEST: [u8; 9] = [b'R', 0, 0, 0, 8, 0, 0, 0, 3];
+
+/// After the request, a `NoticeResponse` that the client waits for in full.
+const STALL_LENGTH: u32 = 0xffff;
+const NOTICE_RESPONSE: u8 = b'N';
+
+/// Answers a startup packet with a slow request for the password, and then an endless notice, so
+/// the client waits whether it answers or not.
+#[derive(Debug, Default)]
+pub struct Postgres {
+    received: Vec<u8>,
+    started: bool,
+    requested: bool,
+    sending: Drip,
+}
+
+impl Postgres {
+    /// Handles a complete message, if we have one. `None` when there isn't.
+    fn receive_message(&mut self) -> Option<Vec<Capture>> {
+        let mut received = self.received.as_slice();
+
+        // the startup packet doesn't have a type
+        let message_type = if self.started {
+            Some(read_u8(&mut received)?)
+        } else {
+            None
+        };
+
+        let length = usize::try_from(read_u32(&mut received)?).unwrap_or(usize::MAX);
+
+        if !(4..=MAX_MESSAGE_LENGTH).contains(&length) {
+            let captures = vec![Capture::new("garbage", &self.received)];
+
+            // give them something to wait for anyway
+            self.received = Vec::new();
+            self.started = true;
+            self.request_password();
+
+            return Some(captures);
+        }
+
+        if received.len() < length - 4 {
+            // wait for the rest
+            return None;
+        }
+
+        let header_length = self.received.len() - received.len();
+
+        let message = self
+            .received
+            .drain(..header_length + length - 4)
+            .collect::<Vec<_>>();
+
+        let body = message.get(header_length..)?;
+
+        let captures = match message_type {
+            None => self.startup(body),
+            Some(b'p') => read_c_string(&mut &*body)
+                .map(|password| vec![Capture::new("password", password)])
+                .unwrap_or_default(),
+            Some(_) => Vec::new(),
+        };
+
+        Some(captures)
+    }
+
+    fn startup(&mut self, mut body: &[u8]) -> Vec<Capture> {
+        match read_u32(&mut body) {
+            Some(SSL_REQUEST | GSS_ENCRYPTION_REQUEST) => {
+                // no, try again without
+                self.sending.push(b"N");
+
+                Vec::new()
+            },
+            Some(PROTOCOL_VERSION) => {
+                self.started = true;
+                self.request_password();
+
+                parse_parameters(body)
+            },
+            Some(_) | None => {
+                self.started = true;
+                self.request_password();
+
+                vec![Capture::new("garbage", body)]
+            },
+        }
+    }
+
+    /// Once, the client's stuck in the notice after that.
+    fn request_password(&mut self) {
+        if self.requested {
+            return;
+        }
+
+        self.requested = true;
+
+        let mut notice = vec![NOTICE_RESPONSE];
+        put_u32(&mut notice, STALL_LENGTH);
+
+        self.sending.push(&PASSWORD_REQUEST);
+        self.sending.push(&notice);
+        self.sending.push_random(STALL_LENGTH - 4);
+    }
+}
+
+impl TarpitProtocol for Postgres {
+    fn tick(&mut self, _treatment: &Treatment) -> Vec<u8> {
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        keep_up_to(&mut self.received, data, MAX_MESSAGE_LENGTH + 5);
+
+        let mut captures = Vec::new();
+
+        while let Some(message_captures) = self.receive_message() {
+            captures.extend(message_captures);
+        }
+
+        captures
+    }
+}
+
+/// The `user` and `database` from the startup packet's name and value pairs.
+fn parse_parameters(mut body: &[u8]) -> Vec<Capture> {
+    let mut captures = Vec::new();
+
+    while let (Some(name), Some(value)) = (read_c_string(&mut body), read_c_string(&mut body)) {
+        match name {
+            b"user" => captures.push(Capture::new("username", value)),
+            b"database" => captures.push(Capture::new("database", value)),
+            _ => {},
+        }
+    }
+
+    captures
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::postgres::{
+        GSS_ENCRYPTION_REQUEST, MAX_MESSAGE_LENGTH, NOTICE_RESPONSE, PASSWORD_REQUEST, Postgres,
+        SSL_REQUEST,
+    };
+    use crate::protocol::wire::put_u32;
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    fn message(message_type: Option<u8>, body: &[u8]) -> Vec<u8> {
+        let mut message = Vec::from_iter(message_type);
+
+        put_u32(&mut message, u32::try_from(body.len() + 4).unwrap());
+        message.extend_from_slice(body);
+
+        message
+    }
+
+    #[test]
+    fn refuses_ssl_and_captures_login() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Postgres, false);
+
+        let mut postgres = Postgres::default();
+
+        assert_eq!(postgres.tick(&treatment), b"", "Waits for the client");
+
+        let mut ssl_request = Vec::new();
+        put_u32(&mut ssl_request, SSL_REQUEST);
+
+        assert_eq!(postgres.receive(&message(None, &ssl_request)), []);
+        assert_eq!(postgres.tick(&treatment), b"N");
+
+        let startup = message(
+            None,
+            &[
+                [0, 3, 0, 0].as_slice(),
+                b"user\0postgres\0database\0prod\0application_name\0psql\0\0",
+            ]
+            .concat(),
+        );
+
+        assert_eq!(
+            postgres.receive(&startup),
+            [
+                Capture::new("username", b"postgres"),
+                Capture::new("database", b"prod"),
+            ]
+        );
+
+        for &byte in &PASSWORD_REQUEST {
+            assert_eq!(postgres.tick(&treatment), [byte], "One byte per tick");
+        }
+
+        assert_eq!(
+            postgres.receive(&message(Some(b'p'), b"hunter2\0")),
+            [Capture::new("password", b"hunter2")]
+        );
+
+        let notice = (0..5)
+            .flat_map(|_| postgres.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(notice, [NOTICE_RESPONSE, 0, 0, 0xff, 0xff]);
+        assert_eq!(postgres.tick(&treatment).len(), 1, "And on it goes");
+    }
+
+    #[test]
+    fn refuses_gss_encryption() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Postgres, false);
+
+        let mut postgres = Postgres::default();
+
+        let mut gss_encryption_request = Vec::new();
+        put_u32(&mut gss_encryption_request, GSS_ENCRYPTION_REQUEST);
+
+        assert_eq!(
+            postgres.receive(&message(None, &gss_encryption_request)),
+            []
+        );
+        assert_eq!(postgres.tick(&treatment), b"N");
+        assert_eq!(
+            postgres.tick(&treatment),
+            b"",
+            "Waits for the startup packet"
+        );
+    }
+
+    #[test]
+    fn captures_lengths_out_of_range_as_garbage() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Postgres, false);
+
+        for length in [0, 3, MAX_MESSAGE_LENGTH + 1] {
+            let mut postgres = Postgres::default();
+
+            let mut startup = Vec::new();
+            put_u32(&mut startup, u32::try_from(length).unwrap());
+            startup.extend_from_slice(&[0, 3, 0, 0]);
+
+            assert_eq!(
+                postgres.receive(&startup),
+                [Capture::new("garbage", &startup)],
+                "Length {}",
+                length
+            );
+            assert_eq!(
+                postgres.tick(&treatment),
+                [PASSWORD_REQUEST[0]],
+                "Still asks for the password"
+            );
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::protocol::http::random_header;
+use crate::protocol::wire::{read_bytes, read_c_string, read_u8, read_u16};
+use crate::protocol::{Capture, Drip, Lines, TarpitProtocol, keep_up_to};
+use crate::rules::Treatment;
+
+/// RFC 1928 3, and RFC 1929 2.
+const SOCKS5: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD: u8 = 2;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const USERNAME_PASSWORD_VERSION: u8 = 1;
+
+/// RFC 1928 5.
+const IPV4: u8 = 1;
+const DOMAIN_NAME: u8 = 3;
+const IPV6: u8 = 4;
+
+/// Succeeded, bound to `0.0.0.0:0`, RFC 1928 6.
+const SOCKS5_REPLY: &[u8] = &[SOCKS5, 0, 0, IPV4, 0, 0, 0, 0, 0, 0];
+
+/// SOCKS 4 and 4A, request granted.
+const SOCKS4: u8 = 4;
+const SOCKS4_REPLY: &[u8] = &[0, 0x5a, 0, 0, 0, 0, 0, 0];
+
+const CONNECTION_ESTABLISHED: &[u8] = b"HTTP/1.1 200 Connection established\r\n\r\n";
+const STATUS_LINE: &[u8] = b"HTTP/1.1 200 OK\r\n";
+
+/// More than any SOCKS request needs.
+const MAX_RECEIVED_LENGTH: usize = 1024;
+
+/// Looks like an open proxy, speaking SOCKS 4 and 5, and HTTP (`CONNECT` and absolute URIs).
+/// Every destination asked for is captured, and the replies are dripped, but nothing ever connects
+/// out: the tunnel leads nowhere, and a forwarded request's response never ends.
+#[derive(Debug, Default)]
+pub struct Proxy {
+    received: Vec<u8>,
+    lines: Lines,
+    stage: Stage,
+    sending: Drip,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+enum Stage {
+    /// The first byte tells which one it is.
+    #[default]
+    Start,
+    Socks5Greeting,
+    Socks5Authentication,
+    Socks5Request,
+    Socks4Request,
+    HttpRequest,
+    HttpHeaders {
+        connect: bool,
+    },
+    /// A tunnel to nowhere.
+    Tunneling,
+    /// After the status line, an endless list of headers.
+    Forwarding,
+}
+
+impl Proxy {
+    /// Handles what the current stage needs, if it's all there.
+    fn receive_stage(&mut self) -> Option<Vec<Capture>> {
+        // out of `self` while the stage reads it, the stages need `self` too
+        let owned = std::mem::take(&mut self.received);
+        let mut received = owned.as_slice();
+
+        let captures = self.read_stage(&mut received);
+
+        let consumed = owned.len() - received.len();
+
+        self.received = owned;
+
+        if captures.is_some() {
+            self.received.drain(..consumed);
+        }
+
+        captures
+    }
+
+    /// Reads what the current stage needs from `received`, `None` when it isn't all there.
+    fn read_stage(&mut self, received: &mut &[u8]) -> Option<Vec<Capture>> {
+        let captures = match self.stage {
+            Stage::Start => {
+                self.stage = match *received.first()? {
+                    SOCKS5 => Stage::Socks5Greeting,
+                    SOCKS4 => Stage::Socks4Request,
+                    _ => Stage::HttpRequest,
+                };
+
+                if self.stage == Stage::HttpRequest {
+                    let request = std::mem::take(received);
+
+                    return Some(self.receive_http(request));
+                }
+
+                Vec::new()
+            },
+            Stage::Socks5Greeting => {
+                let _version: u8 = read_u8(received)?;
+                let count = read_u8(received)?;
+                let methods = read_bytes(received, usize::from(count))?;
+
+                // asking for credentials gets us credentials
+                let method = [USERNAME_PASSWORD, NO_AUTHENTICATION]
+                    .into_iter()
+                    .find(|method| methods.contains(method))
+                    .unwrap_or(NO_ACCEPTABLE_METHODS);
+
+                self.sending.push(&[SOCKS5, method]);
+                self.stage = match method {
+                    USERNAME_PASSWORD => Stage::Socks5Authentication,
+                    NO_AUTHENTICATION => Stage::Socks5Request,
+                    _ => Stage::Tunneling,
+                };
+
+                Vec::new()
+            },
+            Stage::Socks5Authentication => {
+                let _version: u8 = read_u8(received)?;
+                let username_length = read_u8(received)?;
+                let username = read_bytes(received, usize::from(username_length))?;
+                let password_length = read_u8(received)?;
+                let password = read_bytes(received, usize::from(password_length))?;
+
+                self.sending.push(&[USERNAME_PASSWORD_VERSION, 0]);
+                self.stage = Stage::Socks5Request;
+
+                vec![
+                    Capture::new("username", username),
+                    Capture::new("password", password),
+                ]
+            },
+            Stage::Socks5Request => {
+                let _version: u8 = read_u8(received)?;
+                let _command: u8 = read_u8(received)?;
+                let _reserved: u8 = read_u8(received)?;
+
+                let host = match read_u8(received)? {
+                    IPV4 => ipv4(read_bytes(received, 4)?).to_string(),
+                    DOMAIN_NAME => {
+                        let length = read_u8(received)?;
+
+                        String::from_utf8_lossy(read_bytes(received, usize::from(length))?)
+                            .into_owned()
+                    },
+                    IPV6 => {
+                        let octets = <[u8; 16]>::try_from(read_bytes(received, 16)?).ok()?;
+
+                        format!("[{}]", Ipv6Addr::from(octets))
+                    },
+                    // nothing we can reply to, so it waits forever
+                    _ => return None,
+                };
+
+                let port = read_u16(received)?;
+
+                self.sending.push(SOCKS5_REPLY);
+                self.stage = Stage::Tunneling;
+
+                vec![Capture::new(
+                    "destination",
+                    format!("{host}:{port}").as_bytes(),
+                )]
+            },
+            Stage::Socks4Request => {
+                let _version: u8 = read_u8(received)?;
+                let _command: u8 = read_u8(received)?;
+                let port = read_u16(received)?;
+                let address = ipv4(read_bytes(received, 4)?);
+                let user_id = read_c_string(received)?;
+
+                // SOCKS 4A's `0.0.0.x` means the host name follows
+                let destination = match address.octets() {
+                    [0, 0, 0, last] if last != 0 => format!(
+                        "{}:{port}",
+                        String::from_utf8_lossy(read_c_string(received)?)
+                    ),
+                    _ => SocketAddr::from((IpAddr::V4(address), port)).to_string(),
+                };
+
+                self.sending.push(SOCKS4_REPLY);
+                self.stage = Stage::Tunneling;
+
+                let mut captures = Vec::new();
+
+                if !user_id.is_empty() {
+                    captures.push(Capture::new("username", user_id));
+                }
+
+                captures.push(Capture::new("destination", destination.as_bytes()));
+
+                captures
+            },
+            Stage::HttpRequest
+            | Stage::HttpHeaders { .. }
+            | Stage::Tunneling
+            | Stage::Forwarding => return None,
+        };
+
+        Some(captures)
+    }
+
+    fn receive_http(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for line in self.lines.push(data) {
+            match self.stage {
+                Stage::HttpRequest => {
+                    if line.is_empty() {
+                        continue;
+                    }
+
+                    captures.push(Capture::new("request_line", &line));
+
+                    let mut parts = line.split(|&byte| byte == b' ');
+                    let method = parts.next().unwrap_or_default();
+                    let target = parts.next().unwrap_or_default();
+
+                    let connect = method.eq_ignore_ascii_case(b"CONNECT");
+
+                    if connect {
+                        captures.push(Capture::new("destination", target));
+                    } else if let Some(authority) = absolute_authority(target) {
+                        captures.push(Capture::new("destination", authority));
+                    } else {
+                        // not for a proxy
+                    }
+
+                    self.stage = Stage::HttpHeaders { connect };
+                },
+                Stage::HttpHeaders { connect } => {
+                    if !line.is_empty() {
+                        continue;
+                    }
+
+                    if connect {
+                        self.sending.push(CONNECTION_ESTABLISHED);
+                        self.stage = Stage::Tunneling;
+                    } else {
+                        self.sending.push(STATUS_LINE);
+                        self.stage = Stage::Forwarding;
+                    }
+                },
+                Stage::Start
+                | Stage::Socks5Greeting
+                | Stage::Socks5Authentication
+                | Stage::Socks5Request
+                | Stage::Socks4Request
+                | Stage::Tunneling
+                | Stage::Forwarding => break,
+            }
+        }
+
+        captures
+    }
+}
+
+fn ipv4(octets: &[u8]) -> Ipv4Addr {
+    <[u8; 4]>::try_from(octets).map_or(Ipv4Addr::UNSPECIFIED, Ipv4Addr::from)
+}
+
+/// `example.com:8080` from `http://example.com:8080/path`.
+fn absolute_authority(target: &[u8]) -> Option<&[u8]> {
+    let scheme_end = target.windows(3).position(|window| window == b"://")?;
+
+    let authority = target.get(scheme_end + 3..)?;
+
+    let end = authority
+        .iter()
+        .position(|&byte| byte == b'/')
+        .unwrap_or(authority.len());
+
+    authority.get(..end)
+}
+
+impl TarpitProtocol for Proxy {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        if self.stage == Stage::Forwarding && self.sending.is_empty() {
+            return random_header(treatment.max_line_length.get().into());
+        }
+
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        match self.stage {
+            Stage::HttpRequest | Stage::HttpHeaders { .. } => return self.receive_http(data),
+            // whatever they send through the tunnel goes nowhere
+            Stage::Tunneling | Stage::Forwarding => return Vec::new(),
+            Stage::Start
+            | Stage::Socks5Greeting
+            | Stage::Socks5Authentication
+            | Stage::Socks5Request
+            | Stage::Socks4Request => {},
+        }
+
+        keep_up_to(&mut self.received, data, MAX_RECEIVED_LENGTH);
+
+        let mut captures = Vec::new();
+
+        while let Some(stage_captures) = self.receive_stage() {
+            captures.extend(stage_captures);
+        }
+
+        captures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::proxy::Proxy;
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    fn ticks(proxy: &mut Proxy, count: usize) -> Vec<u8> {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Proxy, false);
+
+        std::iter::repeat_with(|| proxy.tick(&treatment))
+            .take(count)
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn socks5() {
+        let mut proxy = Proxy::default();
+
+        assert_eq!(proxy.receive(&[5, 2, 0, 2]), []);
+        assert_eq!(ticks(&mut proxy, 2), [5, 2], "Username and password");
+        assert_eq!(
+            proxy.receive(b"\x01\x05admin\x06secret"),
+            [
+                Capture::new("username", b"admin"),
+                Capture::new("password", b"secret"),
+            ]
+        );
+        assert_eq!(ticks(&mut proxy, 2), [1, 0], "Authenticated");
+
+        let (first, second) = b"\x05\x01\x00\x03\x0bexample.com\x01\xbb".split_at(9);
+
+        assert_eq!(proxy.receive(first), [], "Waits for all of it");
+        assert_eq!(
+            proxy.receive(second),
+            [Capture::new("destination", b"example.com:443")]
+        );
+        assert_eq!(ticks(&mut proxy, 11), [5, 0, 0, 1, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(proxy.receive(b"GET / HTTP/1.1\r\n"), [], "Goes nowhere");
+    }
+
+    #[test]
+    fn socks4() {
+        let mut proxy = Proxy::default();
+
+        assert_eq!(
+            proxy.receive(b"\x04\x01\x00\x50\x5d\xb8\xd8\x22bot\0"),
+            [
+                Capture::new("username", b"bot"),
+                Capture::new("destination", b"93.184.216.34:80"),
+            ]
+        );
+        assert_eq!(ticks(&mut proxy, 8), [0, 0x5a, 0, 0, 0, 0, 0, 0]);
+
+        let mut proxy = Proxy::default();
+
+        assert_eq!(
+            proxy.receive(b"\x04\x01\x00\x50\x00\x00\x00\x01\0example.com\0"),
+            [Capture::new("destination", b"example.com:80")],
+            "SOCKS 4A"
+        );
+    }
+
+    #[test]
+    fn http() {
+        let mut proxy = Proxy::default();
+
+        assert_eq!(
+            proxy.receive(b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n"),
+            [
+                Capture::new("request_line", b"CONNECT example.com:443 HTTP/1.1"),
+                Capture::new("destination", b"example.com:443"),
+            ]
+        );
+        assert_eq!(
+            ticks(&mut proxy, 40),
+            b"HTTP/1.1 200 Connection established\r\n\r\n"
+        );
+        assert_eq!(ticks(&mut proxy, 1), b"", "A tunnel to nowhere");
+
+        let mut proxy = Proxy::default();
+
+        assert_eq!(
+            proxy.receive(b"GET http://example.com/ip HTTP/1.1\r\n\r\n"),
+            [
+                Capture::new("request_line", b"GET http://example.com/ip HTTP/1.1"),
+                Capture::new("destination", b"example.com"),
+            ]
+        );
+        assert_eq!(ticks(&mut proxy, 17), b"HTTP/1.1 200 OK\r\n");
+        assert!(ticks(&mut proxy, 1).starts_with(b"X-"), "Endless headers");
+    }
+}
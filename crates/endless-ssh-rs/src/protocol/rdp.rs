@@ -0,0 +1,282 @@
+use crate::protocol::tls::Tls;
+use crate::protocol::wire::{
+    put_u16, put_u16_le, put_u32_le, read_bytes, read_u8, read_u16, read_u32_le,
+};
+use crate::protocol::{Capture, Drip, TarpitProtocol, keep_up_to};
+use crate::rules::Treatment;
+
+/// TPKT, RFC 1006 6.
+const TPKT_VERSION: u8 = 3;
+const TPKT_HEADER_LENGTH: usize = 4;
+
+/// X.224 TPDU codes, and the data TPDU's header.
+const CONNECTION_REQUEST: u8 = 0xe0;
+const CONNECTION_CONFIRM: u8 = 0xd0;
+const DATA: [u8; 3] = [2, 0xf0, 0x80];
+
+/// `RDP_NEG_REQ` and `RDP_NEG_RSP`, MS-RDPBCGR 2.2.1.1.1 and 2.2.1.2.1.
+const NEGOTIATION_REQUEST: u8 = 1;
+const NEGOTIATION_RESPONSE: u8 = 2;
+/// `EXTENDED_CLIENT_DATA_SUPPORTED`, `DYNVC_GFX_PROTOCOL_SUPPORTED`, `RESTRICTED_ADMIN_MODE_SUPPORTED`
+/// and `REDIRECTED_AUTHENTICATION_MODE_SUPPORTED`, like recent Windows Servers.
+const NEGOTIATION_FLAGS: u8 = 0x1f;
+
+const PROTOCOL_SSL: u32 = 1;
+const PROTOCOL_HYBRID: u32 = 2;
+const PROTOCOLS: [(u32, &str); 4] = [
+    (PROTOCOL_SSL, "ssl"),
+    (PROTOCOL_HYBRID, "hybrid"),
+    (4, "rdstls"),
+    (8, "hybrid_ex"),
+];
+
+const COOKIE: &[u8] = b"Cookie: mstshash=";
+
+/// What we claim our answer to the standard security's `MCS Connect Initial` is.
+const RESPONSE_LENGTH: u16 = 0xffff;
+
+/// Reads the X.224 Connection Request, and drips the Connection Confirm. Clients that wanted TLS
+/// or `CredSSP` then get a TLS handshake that never finishes, the rest an endless `MCS Connect
+/// Response`.
+#[derive(Debug, Default)]
+pub struct Rdp {
+    received: Vec<u8>,
+    requested: bool,
+    sending: Drip,
+    tls: Option<Tls>,
+}
+
+/// What we need from a Connection Request.
+#[derive(Debug, Eq, PartialEq)]
+struct ConnectionRequest<'d> {
+    username: Option<&'d [u8]>,
+    protocols: u32,
+}
+
+impl Rdp {
+    fn confirm(&mut self, requested_protocols: u32) {
+        // like Windows does, it prefers CredSSP, which also starts with TLS
+        let selected = [PROTOCOL_HYBRID, PROTOCOL_SSL]
+            .into_iter()
+            .find(|&protocol| requested_protocols & protocol != 0)
+            .unwrap_or(0);
+
+        let mut confirm = vec![TPKT_VERSION, 0];
+        put_u16(&mut confirm, 19);
+        // length indicator, without itself
+        confirm.extend_from_slice(&[14, CONNECTION_CONFIRM]);
+        // destination and source references, class
+        confirm.extend_from_slice(&[0, 0, 0x12, 0x34, 0]);
+        confirm.extend_from_slice(&[NEGOTIATION_RESPONSE, NEGOTIATION_FLAGS]);
+        put_u16_le(&mut confirm, 8);
+        put_u32_le(&mut confirm, selected);
+
+        self.sending.push(&confirm);
+
+        if selected == 0 {
+            let mut response = vec![TPKT_VERSION, 0];
+            put_u16(&mut response, RESPONSE_LENGTH);
+            response.extend_from_slice(&DATA);
+
+            self.sending.push(&response);
+            self.sending.push_random(u32::from(
+                RESPONSE_LENGTH - u16::try_from(response.len()).expect("7 fits"),
+            ));
+        } else {
+            self.tls = Some(Tls::default());
+        }
+    }
+}
+
+impl TarpitProtocol for Rdp {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        match self.tls.as_mut() {
+            Some(tls) if self.sending.is_empty() => tls.tick(treatment),
+            Some(_) | None => self.sending.pop(),
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.requested {
+            return self
+                .tls
+                .as_mut()
+                .map(|tls| tls.receive(data))
+                .unwrap_or_default();
+        }
+
+        // a TPKT can't be any longer
+        keep_up_to(&mut self.received, data, usize::from(u16::MAX));
+
+        let mut received = self.received.as_slice();
+
+        let (Some(version), Some(_reserved), Some(length)) = (
+            read_u8(&mut received),
+            read_u8(&mut received),
+            read_u16(&mut received),
+        ) else {
+            return Vec::new();
+        };
+
+        let length = usize::from(length);
+
+        let captures = if version == TPKT_VERSION && length >= TPKT_HEADER_LENGTH {
+            let Some(tpdu) = read_bytes(&mut received, length - TPKT_HEADER_LENGTH) else {
+                // wait for the rest
+                return Vec::new();
+            };
+
+            parse_connection_request(tpdu)
+        } else {
+            None
+        };
+
+        self.requested = true;
+
+        let Some(request) = captures else {
+            let captures = vec![Capture::new("garbage", &self.received)];
+
+            self.received = Vec::new();
+            self.confirm(0);
+
+            return captures;
+        };
+
+        let mut captures = Vec::from_iter(
+            request
+                .username
+                .map(|username| Capture::new("username", username)),
+        );
+
+        captures.push(Capture::new(
+            "protocols",
+            protocol_names(request.protocols).as_bytes(),
+        ));
+
+        let protocols = request.protocols;
+
+        self.received = Vec::new();
+        self.confirm(protocols);
+
+        captures
+    }
+}
+
+/// X.224 Connection Request TPDU, with the optional cookie and `RDP_NEG_REQ`.
+fn parse_connection_request(mut tpdu: &[u8]) -> Option<ConnectionRequest<'_>> {
+    let _length_indicator: u8 = read_u8(&mut tpdu)?;
+
+    if read_u8(&mut tpdu)? & 0xf0 != CONNECTION_REQUEST {
+        return None;
+    }
+
+    // destination and source references, class
+    let _ignored: &[u8] = read_bytes(&mut tpdu, 5)?;
+
+    let mut username = None;
+
+    // a cookie, or a routing token, both end in CR LF
+    if tpdu.starts_with(b"Cookie: ") {
+        let end = tpdu.windows(2).position(|window| window == b"\r\n")?;
+
+        let cookie = read_bytes(&mut tpdu, end + 2)?;
+
+        username = cookie
+            .strip_prefix(COOKIE)
+            .and_then(|cookie| cookie.strip_suffix(b"\r\n"));
+    }
+
+    let mut protocols = 0;
+
+    if read_u8(&mut tpdu) == Some(NEGOTIATION_REQUEST) {
+        let _flags: u8 = read_u8(&mut tpdu)?;
+        let _length: &[u8] = read_bytes(&mut tpdu, 2)?;
+
+        protocols = read_u32_le(&mut tpdu)?;
+    }
+
+    Some(ConnectionRequest {
+        username,
+        protocols,
+    })
+}
+
+/// Like `ssl,hybrid`, or `rdp` for standard security only.
+fn protocol_names(protocols: u32) -> String {
+    let names = PROTOCOLS
+        .iter()
+        .filter(|&&(protocol, _)| protocols & protocol != 0)
+        .map(|&(_, name)| name)
+        .collect::<Vec<_>>();
+
+    if names.is_empty() {
+        String::from("rdp")
+    } else {
+        names.join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::rdp::{DATA, Rdp};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    /// What `mstsc` sends, more or less.
+    const CONNECTION_REQUEST: &[u8] = b"\x03\x00\x00\x2f\x2a\xe0\x00\x00\x00\x00\x00Cookie: mstshash=Administr\r\n\x01\x00\x08\x00\x0b\x00\x00\x00";
+
+    #[test]
+    fn confirms_then_stalls_tls() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Rdp, false);
+
+        let mut rdp = Rdp::default();
+
+        assert_eq!(rdp.tick(&treatment), b"", "Waits for the client");
+
+        let (first, second) = CONNECTION_REQUEST.split_at(20);
+
+        assert_eq!(rdp.receive(first), [], "Waits for all of it");
+        assert_eq!(
+            rdp.receive(second),
+            [
+                Capture::new("username", b"Administr"),
+                Capture::new("protocols", b"ssl,hybrid,hybrid_ex"),
+            ]
+        );
+
+        let confirm = (0..19)
+            .flat_map(|_| rdp.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            confirm,
+            b"\x03\x00\x00\x13\x0e\xd0\x00\x00\x12\x34\x00\x02\x1f\x08\x00\x02\x00\x00\x00"
+        );
+
+        assert_eq!(rdp.tick(&treatment), b"", "Waits for the TLS client");
+    }
+
+    #[test]
+    fn stalls_standard_security() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Rdp, false);
+
+        let mut rdp = Rdp::default();
+
+        // no cookie, no negotiation
+        assert_eq!(
+            rdp.receive(b"\x03\x00\x00\x0b\x06\xe0\x00\x00\x00\x00\x00"),
+            [Capture::new("protocols", b"rdp")]
+        );
+
+        let ticks = (0..19 + 7)
+            .flat_map(|_| rdp.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(ticks[15..19], [0, 0, 0, 0], "Standard security");
+        assert_eq!(ticks[19..23], [3, 0, 0xff, 0xff], "Endless TPKT");
+        assert_eq!(ticks[23..], DATA);
+    }
+}
@@ -0,0 +1,332 @@
+use std::num::NonZeroUsize;
+
+use rand::RngExt as _;
+
+use crate::protocol::wire::read_bytes;
+use crate::protocol::{Capture, TarpitProtocol};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// The most we keep of what the client sends, and of a single command for the logs.
+const MAX_RECEIVED_LENGTH: usize = 1 << 16;
+const MAX_COMMAND_LENGTH: usize = 1024;
+
+/// What we claim our replies are, they trickle in over days.
+const BULK_LENGTH: usize = 1 << 20;
+const MULTI_BULK_COUNT: usize = 1 << 20;
+
+/// Fields of `INFO`, with random values.
+const INFO_FIELDS: &[&str] = &[
+    "uptime_in_seconds",
+    "connected_clients",
+    "blocked_clients",
+    "used_memory",
+    "used_memory_peak",
+    "mem_fragmentation_ratio",
+    "rdb_changes_since_last_save",
+    "total_connections_received",
+    "total_commands_processed",
+    "instantaneous_ops_per_sec",
+    "keyspace_hits",
+    "keyspace_misses",
+    "expired_keys",
+    "evicted_keys",
+];
+
+/// Parses RESP commands (and inline ones), and answers the first with a reply that doesn't end:
+/// `INFO` gets a bulk string of fields, anything else an array of random bulk strings.
+#[derive(Debug, Default)]
+pub struct Redis {
+    received: Vec<u8>,
+    reply: Option<Reply>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+enum Reply {
+    BulkHeader,
+    /// Bytes left.
+    Bulk(usize),
+    MultiBulkHeader,
+    /// Elements left.
+    MultiBulk(usize),
+}
+
+impl TarpitProtocol for Redis {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        let max_length = NonZeroUsize::from(treatment.max_line_length).get();
+
+        match self.reply {
+            None => Vec::new(),
+            Some(Reply::BulkHeader) => {
+                let section = b"# Server\r\n";
+
+                self.reply = Some(Reply::Bulk(BULK_LENGTH - section.len()));
+
+                [format!("${BULK_LENGTH}\r\n").as_bytes(), section].concat()
+            },
+            Some(Reply::Bulk(remaining)) => {
+                let mut line = info_line(max_length);
+
+                if line.len() >= remaining {
+                    // days later
+                    line.truncate(remaining);
+                    line.extend_from_slice(b"\r\n");
+
+                    self.reply = None;
+                } else {
+                    self.reply = Some(Reply::Bulk(remaining - line.len()));
+                }
+
+                line
+            },
+            Some(Reply::MultiBulkHeader) => {
+                self.reply = Some(Reply::MultiBulk(MULTI_BULK_COUNT));
+
+                format!("*{MULTI_BULK_COUNT}\r\n").into_bytes()
+            },
+            Some(Reply::MultiBulk(remaining)) => {
+                self.reply = (remaining > 1).then(|| Reply::MultiBulk(remaining - 1));
+
+                random_bulk_string(max_length)
+            },
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.received.len() + data.len() <= MAX_RECEIVED_LENGTH {
+            self.received.extend_from_slice(data);
+        } else if self.received.is_empty() {
+            return vec![Capture::new(
+                "garbage",
+                data.get(..MAX_COMMAND_LENGTH).unwrap_or(data),
+            )];
+        } else {
+            // try what we have
+        }
+
+        let mut captures = Vec::new();
+        let mut received = self.received.as_slice();
+
+        while let Some(arguments) = read_command(&mut received) {
+            if arguments.is_empty() {
+                continue;
+            }
+
+            let mut command = arguments.join(b" ".as_slice());
+            command.truncate(MAX_COMMAND_LENGTH);
+
+            captures.push(Capture::new("command", &command));
+
+            // the rest wait for the first reply, like pipelined commands do
+            if self.reply.is_none() {
+                self.reply = if arguments
+                    .first()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(b"info"))
+                {
+                    Some(Reply::BulkHeader)
+                } else {
+                    Some(Reply::MultiBulkHeader)
+                };
+            }
+        }
+
+        let consumed = self.received.len() - received.len();
+
+        if consumed == 0 && self.received.len() == MAX_RECEIVED_LENGTH {
+            captures.push(Capture::new(
+                "garbage",
+                self.received.get(..MAX_COMMAND_LENGTH).unwrap_or_default(),
+            ));
+
+            self.received.clear();
+        } else {
+            self.received.drain(..consumed);
+        }
+
+        captures
+    }
+}
+
+/// Up to LF, without the line ending.
+fn read_line<'d>(data: &mut &'d [u8]) -> Option<&'d [u8]> {
+    let end = data.iter().position(|&byte| byte == b'\n')?;
+
+    let line = read_bytes(data, end + 1)?;
+
+    line.strip_suffix(b"\r\n")
+        .or_else(|| line.strip_suffix(b"\n"))
+}
+
+fn parse_number(digits: &[u8]) -> Option<usize> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// An array of bulk strings, or an inline command. `None` until all of it is there.
+fn read_command<'d>(data: &mut &'d [u8]) -> Option<Vec<&'d [u8]>> {
+    let mut rest = *data;
+
+    let line = read_line(&mut rest)?;
+
+    let arguments = if let Some(count) = line.strip_prefix(b"*") {
+        let count = parse_number(count)?;
+
+        std::iter::repeat_with(|| {
+            let length = parse_number(read_line(&mut rest)?.strip_prefix(b"$")?)?;
+            let argument = read_bytes(&mut rest, length)?;
+
+            read_line(&mut rest)?;
+
+            Some(argument)
+        })
+        .take(count)
+        .collect::<Option<Vec<_>>>()?
+    } else {
+        line.split(u8::is_ascii_whitespace)
+            .filter(|argument| !argument.is_empty())
+            .collect()
+    };
+
+    *data = rest;
+
+    Some(arguments)
+}
+
+/// Like `used_memory:1048576`, CR LF included.
+fn info_line(max_length: usize) -> Vec<u8> {
+    with_rng(|rng| {
+        let field = INFO_FIELDS[rng.random_range(0..INFO_FIELDS.len())];
+
+        let digits = rng.random_range(1..=max_length.saturating_sub(field.len() + 3).max(1));
+
+        let mut line = format!("{field}:").into_bytes();
+
+        line.extend(std::iter::repeat_with(|| rng.random_range(b'0'..=b'9')).take(digits));
+        line.extend_from_slice(b"\r\n");
+
+        line
+    })
+}
+
+/// Like `$5\r\nabcde\r\n`, at most `max_length` long, but at least 7.
+fn random_bulk_string(max_length: usize) -> Vec<u8> {
+    with_rng(|rng| {
+        let length = rng.random_range(1..=max_bulk_length(max_length));
+
+        let mut bulk_string = format!("${length}\r\n").into_bytes();
+
+        bulk_string.extend(std::iter::repeat_with(|| rng.random_range(b'a'..=b'z')).take(length));
+        bulk_string.extend_from_slice(b"\r\n");
+
+        bulk_string
+    })
+}
+
+/// The longest bulk string data that, with its header, fits in `max_length`. At least 1.
+fn max_bulk_length(max_length: usize) -> usize {
+    // `$` and 2 CR LF, the length's digits come out of what's left
+    let available = max_length.saturating_sub(5);
+
+    available.saturating_sub(available.to_string().len()).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::{Config, MAX_LINE_LENGTH_LIMIT};
+    use crate::protocol::redis::{
+        BULK_LENGTH, MULTI_BULK_COUNT, Redis, max_bulk_length, random_bulk_string,
+    };
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn info_never_ends() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Redis, false);
+
+        let mut redis = Redis::default();
+
+        assert_eq!(redis.tick(&treatment), b"", "Waits for a command");
+
+        assert_eq!(redis.receive(b"*1\r\n$4\r\nIN"), [], "Waits for all of it");
+        assert_eq!(
+            redis.receive(b"FO\r\n*3\r\n$6\r\nCONFIG\r\n$3\r\nSET\r\n$3\r\ndir\r\n"),
+            [
+                Capture::new("command", b"INFO"),
+                Capture::new("command", b"CONFIG SET dir"),
+            ]
+        );
+
+        assert_eq!(
+            redis.tick(&treatment),
+            format!("${BULK_LENGTH}\r\n# Server\r\n").as_bytes()
+        );
+
+        for _ in 0..100 {
+            let line = redis.tick(&treatment);
+
+            assert!(line.contains(&b':'), "Field");
+            assert!(line.ends_with(b"\r\n"), "CR LF");
+            assert!(
+                line.len() <= usize::from(treatment.max_line_length.get()),
+                "Max line length"
+            );
+        }
+    }
+
+    #[test]
+    fn others_get_an_endless_array() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Redis, false);
+
+        let mut redis = Redis::default();
+
+        assert_eq!(
+            redis.receive(b"config get *\r\n"),
+            [Capture::new("command", b"config get *")]
+        );
+
+        assert_eq!(
+            redis.tick(&treatment),
+            format!("*{MULTI_BULK_COUNT}\r\n").as_bytes()
+        );
+
+        for _ in 0..100 {
+            let bulk_string = redis.tick(&treatment);
+
+            assert!(bulk_string.starts_with(b"$"), "Bulk string");
+            assert!(
+                bulk_string.len() <= usize::from(treatment.max_line_length.get()),
+                "Max line length"
+            );
+        }
+    }
+
+    #[test]
+    fn bulk_strings_fit_long_lines() {
+        for max_length in [
+            7,
+            10,
+            15,
+            105,
+            1005,
+            10_006,
+            usize::from(MAX_LINE_LENGTH_LIMIT),
+        ] {
+            let length = max_bulk_length(max_length);
+
+            assert!(
+                1 + length.to_string().len() + 2 + length + 2 <= max_length,
+                "{} fits in {}",
+                length,
+                max_length
+            );
+
+            for _ in 0..100 {
+                assert!(
+                    random_bulk_string(max_length).len() <= max_length,
+                    "Max line length"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use crate::protocol::http::{random_header, split_header};
+use crate::protocol::{Capture, Lines, TarpitProtocol};
+use crate::rules::Treatment;
+
+const TRYING: &[u8] = b"SIP/2.0 100 Trying\r\n";
+const SESSION_PROGRESS: &[u8] = b"SIP/2.0 183 Session Progress\r\n";
+
+/// The headers a response copies from the request, RFC 3261 8.2.6.2, with their compact forms.
+const COPIED_HEADERS: &[&[u8]] = &[
+    b"via", b"v", b"from", b"f", b"to", b"t", b"call-id", b"i", b"cseq",
+];
+
+/// Plenty for a few proxies' `Via`s.
+const MAX_COPIED_HEADERS: usize = 16;
+
+/// Waits for the request (usually `OPTIONS` or `REGISTER`), answers `100 Trying` and then a
+/// provisional response that never ends, one header per tick.
+#[derive(Debug, Default)]
+pub struct Sip {
+    lines: Lines,
+    state: State,
+    copied_headers: Vec<Vec<u8>>,
+    sending: VecDeque<Vec<u8>>,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+enum State {
+    /// Nothing to answer yet.
+    #[default]
+    Waiting,
+    /// Got the request line, reading the headers.
+    Requested,
+    /// Got all headers.
+    Read,
+}
+
+impl Sip {
+    fn respond(&mut self) {
+        let copied_headers = std::mem::take(&mut self.copied_headers);
+
+        let mut trying = TRYING.to_vec();
+
+        for header in &copied_headers {
+            trying.extend_from_slice(header);
+            trying.extend_from_slice(b"\r\n");
+        }
+
+        trying.extend_from_slice(b"Content-Length: 0\r\n\r\n");
+
+        self.sending.push_back(trying);
+        self.sending.push_back(SESSION_PROGRESS.to_vec());
+        self.sending.extend(
+            copied_headers
+                .into_iter()
+                .map(|header| [header.as_slice(), b"\r\n"].concat()),
+        );
+    }
+}
+
+impl TarpitProtocol for Sip {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        if self.state != State::Read {
+            return Vec::new();
+        }
+
+        self.sending
+            .pop_front()
+            .unwrap_or_else(|| random_header(treatment.max_line_length.get().into()))
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for line in self.lines.push(data) {
+            match self.state {
+                State::Waiting => {
+                    // keep-alives are empty lines, RFC 5626 4.4.1
+                    if !line.is_empty() {
+                        captures.push(Capture::new("request_line", &line));
+
+                        self.state = State::Requested;
+                    }
+                },
+                State::Requested => {
+                    if line.is_empty() {
+                        self.state = State::Read;
+                        self.respond();
+
+                        continue;
+                    }
+
+                    let Some((name, value)) = split_header(&line) else {
+                        // not a header
+                        continue;
+                    };
+
+                    let name = name.trim_ascii();
+
+                    if name.eq_ignore_ascii_case(b"user-agent") {
+                        captures.push(Capture::new("user_agent", value));
+                    } else if name.eq_ignore_ascii_case(b"from") || name.eq_ignore_ascii_case(b"f")
+                    {
+                        captures.push(Capture::new("from", value));
+                    } else if name.eq_ignore_ascii_case(b"to") || name.eq_ignore_ascii_case(b"t") {
+                        captures.push(Capture::new("to", value));
+                    } else {
+                        // not interesting
+                    }
+
+                    if COPIED_HEADERS
+                        .iter()
+                        .any(|copied| name.eq_ignore_ascii_case(copied))
+                        && self.copied_headers.len() < MAX_COPIED_HEADERS
+                    {
+                        self.copied_headers.push(line);
+                    }
+                },
+                // the response never ends, so there's no next request
+                State::Read => break,
+            }
+        }
+
+        captures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::http::split_header;
+    use crate::protocol::sip::Sip;
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn tries_forever() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Sip, false);
+
+        let mut sip = Sip::default();
+
+        assert_eq!(sip.tick(&treatment), b"", "Waits for the request");
+        assert_eq!(
+            sip.receive(b"OPTIONS sip:100@192.0.2.1 SIP/2.0\r\nVia: SIP/2.0/TCP 198.51.100.7:5060;branch=z9hG4bK-1\r\nf: \"sipvicious\"<sip:100@1.1.1.1>;tag=6631\r\nTo: \"sipvicious\"<sip:100@1.1.1.1>\r\nUser-Agent: friendly-scanner\r\nCall-ID: 1234\r\nCSeq: 1 OPTIONS\r\nContent-Length: 0\r\n\r\n"),
+            [
+                Capture::new("request_line", b"OPTIONS sip:100@192.0.2.1 SIP/2.0"),
+                Capture::new("from", b"\"sipvicious\"<sip:100@1.1.1.1>;tag=6631"),
+                Capture::new("to", b"\"sipvicious\"<sip:100@1.1.1.1>"),
+                Capture::new("user_agent", b"friendly-scanner"),
+            ]
+        );
+
+        assert_eq!(
+            sip.tick(&treatment),
+            b"SIP/2.0 100 Trying\r\nVia: SIP/2.0/TCP 198.51.100.7:5060;branch=z9hG4bK-1\r\nf: \"sipvicious\"<sip:100@1.1.1.1>;tag=6631\r\nTo: \"sipvicious\"<sip:100@1.1.1.1>\r\nCall-ID: 1234\r\nCSeq: 1 OPTIONS\r\nContent-Length: 0\r\n\r\n"
+        );
+        assert_eq!(sip.tick(&treatment), b"SIP/2.0 183 Session Progress\r\n");
+
+        for header in ["Via", "f", "To", "Call-ID", "CSeq"] {
+            assert!(
+                sip.tick(&treatment).starts_with(header.as_bytes()),
+                "Copied {header}"
+            );
+        }
+
+        for _ in 0..100 {
+            let header = sip.tick(&treatment);
+
+            assert!(header.starts_with(b"X-"), "Header name");
+            assert!(split_header(&header).is_some(), "Header");
+        }
+    }
+}
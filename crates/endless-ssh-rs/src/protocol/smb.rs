@@ -0,0 +1,68 @@
+use crate::protocol::wire::put_u24;
+use crate::protocol::{Capture, Drip, TarpitProtocol};
+use crate::rules::Treatment;
+
+/// What we claim our `NEGOTIATE` response is, the client waits for all of it.
+const RESPONSE_LENGTH: u32 = 0xffff;
+
+/// `SMB2` header protocol ID.
+const SMB2: &[u8] = b"\xfeSMB";
+
+/// Waits for the client's first message, and answers with a response (framed like RFC 1002 4.3.1,
+/// direct TCP) that never finishes, one byte per tick.
+#[derive(Debug, Default)]
+pub struct Smb {
+    requested: bool,
+    sending: Drip,
+}
+
+impl TarpitProtocol for Smb {
+    fn tick(&mut self, _treatment: &Treatment) -> Vec<u8> {
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.requested || data.is_empty() {
+            return Vec::new();
+        }
+
+        self.requested = true;
+
+        let mut header = vec![0];
+        put_u24(&mut header, RESPONSE_LENGTH);
+        header.extend_from_slice(SMB2);
+
+        self.sending.push(&header);
+        self.sending
+            .push_random(RESPONSE_LENGTH - u32::try_from(SMB2.len()).expect("4 fits"));
+
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::smb::Smb;
+    use crate::protocol::{Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn answers_slowly() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Smb, false);
+
+        let mut smb = Smb::default();
+
+        assert_eq!(smb.tick(&treatment), b"", "Waits for the client");
+        assert_eq!(smb.receive(b"\0\0\0\x45\xffSMBr"), []);
+
+        let response = (0..9)
+            .flat_map(|_| smb.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(response[..8], *b"\0\0\xff\xff\xfeSMB");
+        assert_eq!(response.len(), 9, "And on it goes");
+    }
+}
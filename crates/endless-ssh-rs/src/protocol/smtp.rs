@@ -0,0 +1,103 @@
+use std::num::NonZeroUsize;
+
+use crate::protocol::{Capture, Lines, TarpitProtocol};
+use crate::rules::Treatment;
+
+const REPLY_CODE: &[u8] = b"220-";
+
+/// The reply code, a character and CR LF.
+pub const MIN_LINE_LENGTH: u16 = 7;
+
+/// A `220-` greeting that never gets to its last line (RFC 5321 4.2.1), so the client never gets to
+/// say `EHLO`. Impatient clients that do are captured, and ignored.
+#[derive(Debug, Default)]
+pub struct Smtp {
+    lines: Lines,
+}
+
+impl Smtp {
+    fn greeting_line(treatment: &Treatment) -> Vec<u8> {
+        let max_length = NonZeroUsize::from(treatment.max_line_length).get();
+
+        // at least `MIN_LINE_LENGTH`, that's checked when parsing
+        let text = treatment.line(max_length.saturating_sub(REPLY_CODE.len()).max(3));
+
+        [REPLY_CODE, &text].concat()
+    }
+}
+
+impl TarpitProtocol for Smtp {
+    fn setup(&mut self) -> Vec<u8> {
+        // tell them who we are right away, so they stay
+        b"220-ESMTP ready\r\n".to_vec()
+    }
+
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        Smtp::greeting_line(treatment)
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        self.lines
+            .push(data)
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .map(|line| Capture::new("command", &line))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU16;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::smtp::{MIN_LINE_LENGTH, Smtp};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn greeting_never_ends() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Smtp, false);
+
+        let mut smtp = Smtp::default();
+
+        assert_eq!(smtp.setup(), b"220-ESMTP ready\r\n");
+
+        for _ in 0..100 {
+            let line = smtp.tick(&treatment);
+
+            assert!(line.starts_with(b"220-"), "Continuation");
+            assert!(line.ends_with(b"\r\n"), "CR LF");
+            assert!(
+                line.len() <= usize::from(treatment.max_line_length.get()),
+                "Max line length"
+            );
+        }
+    }
+
+    #[test]
+    fn captures_early_commands() {
+        let mut smtp = Smtp::default();
+
+        assert_eq!(
+            smtp.receive(b"EHLO spam.example\r\nMAIL FROM:<a@spam.example>\r\n"),
+            [
+                Capture::new("command", b"EHLO spam.example"),
+                Capture::new("command", b"MAIL FROM:<a@spam.example>"),
+            ]
+        );
+    }
+
+    #[test]
+    fn shortest_lines_fit() {
+        let mut treatment = Treatment::defaults(&Config::default(), Protocol::Smtp, false);
+
+        treatment.max_line_length = NonZeroU16::new(MIN_LINE_LENGTH).unwrap();
+
+        for _ in 0..100 {
+            assert_eq!(Smtp::greeting_line(&treatment).len(), 7, "Fits exactly");
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use rand::RngExt as _;
+
+use crate::protocol::wire::{put_u32, read_bytes, read_u32};
+use crate::protocol::{Capture, Drip, Lines, TarpitProtocol};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// What a recent Ubuntu says.
+const IDENTIFICATION: &[u8] = b"SSH-2.0-OpenSSH_9.6p1 Ubuntu-3ubuntu13.5\r\n";
+
+/// OpenSSH drops lines before the version that are longer (`SSH_MAX_BANNER_LEN` in kex.c).
+pub const MAX_LINE_LENGTH: u16 = 8192;
+
+/// RFC 4253 6.1, what we need to accept at least.
+const MAX_PACKET_LENGTH: usize = 35000;
+
+/// RFC 4253 12, and RFC 5656 7.1.
+const SSH_MSG_KEXINIT: u8 = 20;
+const SSH_MSG_KEX_ECDH_REPLY: u8 = 31;
+
+/// Our reply's `packet_length`, so it's 35000 in total, a multiple of 8.
+const REPLY_PACKET_LENGTH: u32 = 34996;
+const REPLY_PADDING_LENGTH: u8 = 4;
+
+/// Like OpenSSH 9.6.
+const KEX_ALGORITHMS: &str = "sntrup761x25519-sha512@openssh.com,curve25519-sha256,curve25519-sha256@libssh.org,ecdh-sha2-nistp256,ecdh-sha2-nistp384,ecdh-sha2-nistp521,diffie-hellman-group-exchange-sha256,diffie-hellman-group16-sha512,diffie-hellman-group18-sha512,diffie-hellman-group14-sha256,ext-info-s,kex-strict-s-v00@openssh.com";
+const HOST_KEY_ALGORITHMS: &str = "rsa-sha2-512,rsa-sha2-256,ecdsa-sha2-nistp256,ssh-ed25519";
+const CIPHERS: &str = "chacha20-poly1305@openssh.com,aes128-ctr,aes192-ctr,aes256-ctr,aes128-gcm@openssh.com,aes256-gcm@openssh.com";
+const MACS: &str = "umac-64-etm@openssh.com,umac-128-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-512-etm@openssh.com,hmac-sha1-etm@openssh.com,umac-64@openssh.com,umac-128@openssh.com,hmac-sha2-256,hmac-sha2-512,hmac-sha1";
+const COMPRESSION: &str = "none,zlib@openssh.com";
+
+/// The original. RFC 4253 4.2 allows the server to send other lines before its version, so we
+/// never get to the version.
+///
+/// Clients send their identification right away, which we capture.
+#[derive(Debug, Default)]
+pub struct SshBanner {
+    lines: Lines,
+    identified: bool,
+    pre_banner_sent: usize,
+}
+
+impl TarpitProtocol for SshBanner {
+    fn tick(&mut self, treatment: &Treatment) -> Vec<u8> {
+        if let Some(line) = treatment.pre_banner.get(self.pre_banner_sent) {
+            self.pre_banner_sent += 1;
+
+            return format!("{line}\r\n").into_bytes();
+        }
+
+        treatment.line(treatment.max_line_length.get().into())
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.identified {
+            // after that, they're just waiting for us
+            return Vec::new();
+        }
+
+        let Some(identification) = self.lines.push(data).into_iter().next() else {
+            return Vec::new();
+        };
+
+        self.identified = true;
+
+        if identification.starts_with(b"SSH-") {
+            vec![Capture::new("client_version", &identification)]
+        } else {
+            vec![Capture::new("garbage", &identification)]
+        }
+    }
+}
+
+/// A well-behaved server, up to the key exchange. After the identifications we wait for the client's
+/// `SSH_MSG_KEXINIT`, and answer with ours, one byte per tick. After that, it's the largest key
+/// exchange reply the client has to accept, which it waits for in full. Not `SSH_MSG_IGNORE`s, as
+/// strict key exchange (which we advertise, like OpenSSH does) doesn't allow those.
+#[derive(Debug, Default)]
+pub struct SshKexStall {
+    received: Vec<u8>,
+    identified: bool,
+    kexinit_received: bool,
+    kexinit_sent: bool,
+    sending: Drip,
+}
+
+impl SshKexStall {
+    /// Handles a complete packet from the client, if we have one.
+    fn receive_packet(&mut self) -> Option<Vec<Capture>> {
+        let mut rest = self.received.as_slice();
+
+        let packet_length = usize::try_from(read_u32(&mut rest)?).unwrap_or(usize::MAX);
+
+        if packet_length > MAX_PACKET_LENGTH {
+            // don't wait for something that's never going to fit
+            self.kexinit_received = true;
+
+            return Some(vec![Capture::new("garbage", &self.received)]);
+        }
+
+        let packet = rest.get(..packet_length)?;
+
+        let captures = parse_kexinit(packet)
+            .map(|algorithms| vec![Capture::new("hassh_algorithms", algorithms.as_bytes())])
+            .unwrap_or_default();
+
+        self.kexinit_received = true;
+
+        Some(captures)
+    }
+}
+
+impl TarpitProtocol for SshKexStall {
+    fn setup(&mut self) -> Vec<u8> {
+        IDENTIFICATION.to_vec()
+    }
+
+    fn tick(&mut self, _treatment: &Treatment) -> Vec<u8> {
+        if !self.kexinit_received {
+            return Vec::new();
+        }
+
+        if self.sending.is_empty() {
+            if self.kexinit_sent {
+                // the client's long gone by the time we get here again
+                self.sending.push(&reply_header());
+                self.sending.push_random(REPLY_PACKET_LENGTH - 2);
+            } else {
+                self.kexinit_sent = true;
+                self.sending.push(&packet(&kexinit_payload()));
+            }
+        }
+
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.kexinit_received {
+            // they're waiting for us now
+            return Vec::new();
+        }
+
+        if self.received.len() + data.len() <= MAX_PACKET_LENGTH + 4 {
+            self.received.extend_from_slice(data);
+        }
+
+        let mut captures = Vec::new();
+
+        if !self.identified {
+            let Some(end) = self.received.iter().position(|&byte| byte == b'\n') else {
+                return captures;
+            };
+
+            let line = self.received.drain(..=end).collect::<Vec<_>>();
+
+            let identification = line
+                .strip_suffix(b"\r\n")
+                .or_else(|| line.strip_suffix(b"\n"))
+                .unwrap_or(&line);
+
+            self.identified = true;
+
+            captures.push(Capture::new("client_version", identification));
+        }
+
+        captures.extend(self.receive_packet().into_iter().flatten());
+
+        captures
+    }
+}
+
+/// RFC 4251 5.
+fn put_string(buffer: &mut Vec<u8>, string: &[u8]) {
+    put_u32(
+        buffer,
+        u32::try_from(string.len()).expect("Our strings are short"),
+    );
+    buffer.extend_from_slice(string);
+}
+
+/// RFC 4251 5.
+fn read_string<'d>(data: &mut &'d [u8]) -> Option<&'d [u8]> {
+    let length = usize::try_from(read_u32(data)?).ok()?;
+
+    read_bytes(data, length)
+}
+
+/// The algorithms HASSH hashes, `kex;ciphers;macs;compression`, all client to server.
+fn parse_kexinit(packet: &[u8]) -> Option<String> {
+    let (&padding_length, rest) = packet.split_first()?;
+    let payload = rest.get(..rest.len().checked_sub(usize::from(padding_length))?)?;
+
+    let (&message, payload) = payload.split_first()?;
+
+    if message != SSH_MSG_KEXINIT {
+        return None;
+    }
+
+    // skip the cookie
+    let mut payload = payload.get(16..)?;
+
+    let kex = read_string(&mut payload)?;
+    let _host_key: &[u8] = read_string(&mut payload)?;
+    let ciphers = read_string(&mut payload)?;
+    let _ciphers_server_to_client: &[u8] = read_string(&mut payload)?;
+    let macs = read_string(&mut payload)?;
+    let _macs_server_to_client: &[u8] = read_string(&mut payload)?;
+    let compression = read_string(&mut payload)?;
+
+    Some(
+        [kex, ciphers, macs, compression]
+            .map(String::from_utf8_lossy)
+            .join(";"),
+    )
+}
+
+/// RFC 4253 7.1.
+fn kexinit_payload() -> Vec<u8> {
+    let mut payload = vec![SSH_MSG_KEXINIT];
+
+    let mut cookie = [0_u8; 16];
+    with_rng(|rng| rng.fill(&mut cookie));
+    payload.extend_from_slice(&cookie);
+
+    for name_list in [
+        KEX_ALGORITHMS,
+        HOST_KEY_ALGORITHMS,
+        CIPHERS,
+        CIPHERS,
+        MACS,
+        MACS,
+        COMPRESSION,
+        COMPRESSION,
+        "",
+        "",
+    ] {
+        put_string(&mut payload, name_list.as_bytes());
+    }
+
+    // first_kex_packet_follows
+    payload.push(0);
+    // reserved
+    put_u32(&mut payload, 0);
+
+    payload
+}
+
+/// Up to the message type, the rest of the payload and the padding are random, byte by byte.
+fn reply_header() -> Vec<u8> {
+    let mut header = Vec::new();
+
+    put_u32(&mut header, REPLY_PACKET_LENGTH);
+    header.push(REPLY_PADDING_LENGTH);
+    header.push(SSH_MSG_KEX_ECDH_REPLY);
+
+    header
+}
+
+/// RFC 4253 6, without encryption or MAC, as that's not negotiated yet.
+fn packet(payload: &[u8]) -> Vec<u8> {
+    // the whole packet needs to be a multiple of 8, with at least 4 bytes of padding
+    let mut padding_length = 8 - (4 + 1 + payload.len()) % 8;
+
+    if padding_length < 4 {
+        padding_length += 8;
+    }
+
+    let mut padding = vec![0_u8; padding_length];
+    with_rng(|rng| rng.fill(padding.as_mut_slice()));
+
+    let mut packet = Vec::with_capacity(4 + 1 + payload.len() + padding_length);
+
+    put_u32(
+        &mut packet,
+        u32::try_from(1 + payload.len() + padding_length).expect("Our packets are small"),
+    );
+    packet.push(u8::try_from(padding_length).expect("Less than 12"));
+    packet.extend_from_slice(payload);
+    packet.extend_from_slice(&padding);
+
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    use crate::config::Config;
+    use crate::protocol::ssh::{
+        CIPHERS, COMPRESSION, KEX_ALGORITHMS, MACS, SSH_MSG_KEX_ECDH_REPLY, SSH_MSG_KEXINIT,
+        SshBanner, SshKexStall, kexinit_payload, packet, parse_kexinit,
+    };
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn captures_client_version() {
+        let mut banner = SshBanner::default();
+
+        assert_eq!(banner.receive(b"SSH-2.0-Go"), []);
+        assert_eq!(
+            banner.receive(b"\r\n\x00\x00\x01"),
+            [Capture::new("client_version", b"SSH-2.0-Go")]
+        );
+        assert_eq!(banner.receive(b"\n"), [], "Only the first line");
+    }
+
+    #[test]
+    fn ticks_are_lines() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Ssh, false);
+
+        let mut banner = SshBanner::default();
+
+        let line = banner.tick(&treatment);
+
+        assert!(line.ends_with(b"\r\n"), "CR LF");
+        assert!(!line.starts_with(b"SSH-"), "Not a version");
+        assert!(
+            line.len() <= usize::from(treatment.max_line_length.get()),
+            "Max line length"
+        );
+    }
+
+    #[test]
+    fn banner_sends_pre_banner_first() {
+        let mut treatment = Treatment::defaults(&Config::default(), Protocol::Ssh, false);
+
+        treatment.pre_banner = &["Authorized access only!"];
+
+        let mut banner = SshBanner::default();
+
+        assert_eq!(banner.tick(&treatment), b"Authorized access only!\r\n");
+        assert_ne!(
+            banner.tick(&treatment),
+            b"Authorized access only!\r\n",
+            "Only once"
+        );
+    }
+
+    #[test]
+    fn stalls_after_kexinit() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::SshKex, false);
+
+        let mut stall = SshKexStall::default();
+
+        assert!(
+            stall.setup().starts_with(b"SSH-2.0-OpenSSH_"),
+            "Identification"
+        );
+        assert_eq!(stall.tick(&treatment), b"", "Waits for the client");
+
+        let client_kexinit = packet(&kexinit_payload());
+
+        let captures =
+            stall.receive(&[b"SSH-2.0-Go\r\n".as_slice(), &client_kexinit[..100]].concat());
+
+        assert_eq!(captures, [Capture::new("client_version", b"SSH-2.0-Go")]);
+        assert_eq!(stall.tick(&treatment), b"", "Waits for the whole packet");
+
+        let captures = stall.receive(&client_kexinit[100..]);
+
+        assert_eq!(
+            captures,
+            [Capture::new(
+                "hassh_algorithms",
+                format!("{};{};{};{}", KEX_ALGORITHMS, CIPHERS, MACS, COMPRESSION).as_bytes()
+            )]
+        );
+
+        // same algorithms, so same length
+        let mut dripped = Vec::new();
+
+        while dripped.len() < client_kexinit.len() {
+            let tick = stall.tick(&treatment);
+
+            assert_eq!(tick.len(), 1, "One byte per tick");
+
+            dripped.extend(tick);
+        }
+
+        assert_eq!(dripped.len() % 8, 0, "Block size");
+        assert_eq!(dripped[5], SSH_MSG_KEXINIT);
+        assert!(parse_kexinit(&dripped[4..]).is_some(), "Valid KEXINIT");
+
+        let reply = (0..6)
+            .flat_map(|_| stall.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(reply, [0, 0, 0x88, 0xb4, 4, SSH_MSG_KEX_ECDH_REPLY]);
+        assert_eq!(stall.tick(&treatment).len(), 1, "And on it goes");
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::VecDeque;
+
+use crate::protocol::{Capture, TarpitProtocol};
+use crate::rules::Treatment;
+
+// RFC 854
+const SE: u8 = 240;
+const SB: u8 = 250;
+const WILL: u8 = 251;
+const WONT: u8 = 252;
+const DO: u8 = 253;
+const DONT: u8 = 254;
+const IAC: u8 = 255;
+
+// RFC 857, 858, 1091, 1073
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const TERMINAL_TYPE: u8 = 24;
+const WINDOW_SIZE: u8 = 31;
+
+/// One per tick.
+const NEGOTIATION: [[u8; 3]; 4] = [
+    [IAC, DO, TERMINAL_TYPE],
+    [IAC, DO, WINDOW_SIZE],
+    [IAC, WILL, SUPPRESS_GO_AHEAD],
+    [IAC, WILL, ECHO],
+];
+
+const LOGIN_PROMPT: &[u8] = b"\r\nlogin: ";
+const PASSWORD_PROMPT: &[u8] = b"\r\nPassword: ";
+const LOGIN_INCORRECT: &[u8] = b"\r\nLogin incorrect\r\n";
+
+/// Ticks between the password and `Login incorrect`.
+const FAILURE_TICKS: u32 = 30;
+
+/// What we keep of the peer's input, and of its credentials.
+const MAX_INPUT_LENGTH: usize = 1024;
+const MAX_CREDENTIAL_LENGTH: usize = 128;
+
+/// A `BusyBox` style login. Takes its time negotiating, echoes one character per tick and after
+/// a long wait rejects whatever was entered.
+#[derive(Debug, Default)]
+pub struct Telnet {
+    decoder: Decoder,
+    /// Typed, but not handled yet. Lines end in a single CR.
+    input: VecDeque<u8>,
+    previous_cr: bool,
+    state: State,
+    /// For capturing, as the input comes in.
+    credential: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum State {
+    /// Index into `NEGOTIATION`.
+    Negotiating(usize),
+    Username,
+    Password,
+    Failing(u32),
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Negotiating(0)
+    }
+}
+
+/// Strips telnet commands from the peer's input.
+#[derive(Debug, Default)]
+enum Decoder {
+    #[default]
+    Data,
+    /// After `IAC`.
+    Command,
+    /// After `IAC WILL`, `IAC WONT`, `IAC DO` or `IAC DONT`.
+    Option,
+    /// Between `IAC SB` and `IAC SE`.
+    Subnegotiation,
+    /// After `IAC` in a subnegotiation.
+    SubnegotiationCommand,
+}
+
+impl Decoder {
+    fn decode(&mut self, byte: u8) -> Option<u8> {
+        match (&*self, byte) {
+            (&Decoder::Data, IAC) => *self = Decoder::Command,
+            (&Decoder::Data, _) => return Some(byte),
+            (&Decoder::Command, IAC) => {
+                // escaped
+                *self = Decoder::Data;
+
+                return Some(IAC);
+            },
+            (&Decoder::Command, WILL | WONT | DO | DONT) => *self = Decoder::Option,
+            (&Decoder::Command, SB) => *self = Decoder::Subnegotiation,
+            (&Decoder::Command | &Decoder::Option, _) | (&Decoder::SubnegotiationCommand, SE) => {
+                *self = Decoder::Data;
+            },
+            (&Decoder::Subnegotiation, IAC) => *self = Decoder::SubnegotiationCommand,
+            (&Decoder::Subnegotiation, _) => {},
+            (&Decoder::SubnegotiationCommand, _) => *self = Decoder::Subnegotiation,
+        }
+
+        None
+    }
+}
+
+impl Telnet {
+    /// Keeps track of the lines for capturing, before `byte` gets queued.
+    fn capture(&mut self, byte: u8) -> Option<Capture> {
+        if byte != b'\r' {
+            if self.credential.len() < MAX_CREDENTIAL_LENGTH {
+                self.credential.push(byte);
+            }
+
+            return None;
+        }
+
+        let credential = std::mem::take(&mut self.credential);
+
+        // where the login is once the lines before this one are handled, each line handled
+        // switches between username and password
+        let queued = self.input.iter().filter(|&&byte| byte == b'\r').count();
+
+        let kind = if matches!(self.state, State::Password) == (queued % 2 == 0) {
+            "password"
+        } else {
+            "username"
+        };
+
+        Some(Capture::new(kind, &credential))
+    }
+}
+
+impl TarpitProtocol for Telnet {
+    fn tick(&mut self, _treatment: &Treatment) -> Vec<u8> {
+        match self.state {
+            State::Negotiating(index) => {
+                if let Some(command) = NEGOTIATION.get(index) {
+                    self.state = State::Negotiating(index + 1);
+
+                    command.to_vec()
+                } else {
+                    self.state = State::Username;
+
+                    LOGIN_PROMPT.to_vec()
+                }
+            },
+            State::Username => match self.input.pop_front() {
+                Some(b'\r') => {
+                    self.state = State::Password;
+
+                    PASSWORD_PROMPT.to_vec()
+                },
+                // echo
+                Some(byte @ 32..=126) => vec![byte],
+                Some(_) | None => Vec::new(),
+            },
+            State::Password => {
+                // no echo
+                if self.input.pop_front() == Some(b'\r') {
+                    self.state = State::Failing(FAILURE_TICKS);
+                }
+
+                Vec::new()
+            },
+            State::Failing(0) => {
+                self.state = State::Username;
+
+                [LOGIN_INCORRECT, LOGIN_PROMPT].concat()
+            },
+            State::Failing(ticks) => {
+                self.state = State::Failing(ticks - 1);
+
+                Vec::new()
+            },
+        }
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        let mut captures = Vec::new();
+
+        for &byte in data {
+            let Some(byte) = self.decoder.decode(byte) else {
+                continue;
+            };
+
+            // CR LF, CR NUL and a bare LF all end the line, RFC 854
+            let byte = match (self.previous_cr, byte) {
+                (true, b'\n' | b'\0') => {
+                    self.previous_cr = false;
+
+                    continue;
+                },
+                (_, b'\n') => b'\r',
+                (_, _) => byte,
+            };
+
+            self.previous_cr = byte == b'\r';
+
+            // what doesn't fit is never handled, so not captured either
+            if self.input.len() < MAX_INPUT_LENGTH {
+                captures.extend(self.capture(byte));
+
+                self.input.push_back(byte);
+            }
+        }
+
+        captures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::telnet::{
+        DO, ECHO, FAILURE_TICKS, IAC, SB, SE, TERMINAL_TYPE, Telnet, WILL,
+    };
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn negotiates_prompts_and_fails() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Telnet, false);
+
+        let mut telnet = Telnet::default();
+
+        assert_eq!(telnet.tick(&treatment), [IAC, DO, TERMINAL_TYPE]);
+
+        for _ in 0..2 {
+            telnet.tick(&treatment);
+        }
+
+        assert_eq!(telnet.tick(&treatment), [IAC, WILL, ECHO]);
+        assert_eq!(telnet.tick(&treatment), b"\r\nlogin: ");
+
+        // the client's negotiation, then credentials
+        let captures = telnet.receive(
+            &[
+                &[IAC, WILL, TERMINAL_TYPE, IAC, SB, TERMINAL_TYPE, 0],
+                b"xterm".as_slice(),
+                &[IAC, SE],
+                b"root\r\0xc\n",
+            ]
+            .concat(),
+        );
+
+        assert_eq!(
+            captures,
+            [
+                Capture::new("username", b"root"),
+                Capture::new("password", b"xc"),
+            ]
+        );
+
+        let echoed = (0..5)
+            .flat_map(|_| telnet.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(echoed, b"root\r\nPassword: ");
+
+        for _ in 0..3 {
+            assert_eq!(telnet.tick(&treatment), b"", "No echo");
+        }
+
+        for _ in 0..FAILURE_TICKS {
+            assert_eq!(telnet.tick(&treatment), b"", "Long wait");
+        }
+
+        assert_eq!(
+            telnet.tick(&treatment),
+            b"\r\nLogin incorrect\r\n\r\nlogin: "
+        );
+    }
+
+    #[test]
+    fn captures_lines_after_the_password_for_the_next_login() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Telnet, false);
+
+        let mut telnet = Telnet::default();
+
+        assert_eq!(
+            telnet.receive(b"root\r\n"),
+            [Capture::new("username", b"root")]
+        );
+
+        while telnet.tick(&treatment) != b"\r\nPassword: " {}
+
+        assert_eq!(
+            telnet.receive(b"toor\r\n"),
+            [Capture::new("password", b"toor")]
+        );
+
+        for _ in 0..5 {
+            telnet.tick(&treatment);
+        }
+
+        // while failing, what's typed goes to the next login prompt
+        assert_eq!(
+            telnet.receive(b"id\r\nuname -a\r\nexit\r\n"),
+            [
+                Capture::new("username", b"id"),
+                Capture::new("password", b"uname -a"),
+                Capture::new("username", b"exit"),
+            ]
+        );
+    }
+}
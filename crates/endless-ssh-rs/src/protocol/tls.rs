@@ -0,0 +1,485 @@
+use rand::RngExt as _;
+
+use crate::protocol::wire::{put_u16, put_u24, read_bytes, read_u8, read_u16, read_u24};
+use crate::protocol::{Capture, Drip, TarpitProtocol, keep_up_to};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// RFC 8446 5.1.
+const CONTENT_TYPE_HANDSHAKE: u8 = 22;
+const MAX_RECORD_LENGTH: usize = 1 << 14;
+
+/// RFC 8446 4.
+const CLIENT_HELLO: u8 = 1;
+const SERVER_HELLO: u8 = 2;
+const CERTIFICATE: u8 = 11;
+
+/// RFC 8446 4.2, and RFC 8422 5.1.
+const EXTENSION_SERVER_NAME: u16 = 0;
+const EXTENSION_SUPPORTED_GROUPS: u16 = 10;
+const EXTENSION_EC_POINT_FORMATS: u16 = 11;
+
+/// TLS 1.2, also what TLS 1.3 puts in its records and `ServerHello`s.
+const VERSION: u16 = 0x0303;
+
+/// `TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256`, when the client doesn't offer anything we can pick.
+const FALLBACK_CIPHER_SUITE: u16 = 0xc02f;
+
+/// What we claim our certificate chain is. Below what clients accept (`OpenSSL` allows 100 KiB),
+/// so they wait for all of it.
+const CERTIFICATE_LENGTH: u32 = 60_000;
+
+/// A `ClientHello` larger than this isn't worth waiting for.
+const MAX_HANDSHAKE_LENGTH: usize = 1 << 16;
+
+/// Parses the `ClientHello`, then answers with a `ServerHello` and a `Certificate` in records of a
+/// single byte, one per tick. The certificate is random and never ends, so neither does the
+/// handshake.
+#[derive(Debug, Default)]
+pub struct Tls {
+    /// What we haven't parsed into records yet.
+    received: Vec<u8>,
+    /// Handshake messages from those records.
+    handshake: Vec<u8>,
+    hello_received: bool,
+    /// Handshake bytes to send, one record each.
+    sending: Drip,
+}
+
+/// What we need from a `ClientHello`.
+#[derive(Debug, Eq, PartialEq)]
+struct ClientHello {
+    session_id: Vec<u8>,
+    cipher_suites: Vec<u16>,
+    server_name: Option<Vec<u8>>,
+    ja3: String,
+}
+
+impl Tls {
+    /// Collects handshake messages from complete records, returns `false` when the client doesn't
+    /// speak TLS.
+    fn read_records(&mut self) -> bool {
+        loop {
+            let mut data = self.received.as_slice();
+
+            let (Some(content_type), Some(_version), Some(length)) =
+                (read_u8(&mut data), read_u16(&mut data), read_u16(&mut data))
+            else {
+                return true;
+            };
+
+            let length = usize::from(length);
+
+            if content_type != CONTENT_TYPE_HANDSHAKE || length > MAX_RECORD_LENGTH {
+                return false;
+            }
+
+            let Some(fragment) = read_bytes(&mut data, length) else {
+                return true;
+            };
+
+            if self.handshake.len() + fragment.len() > MAX_HANDSHAKE_LENGTH {
+                return false;
+            }
+
+            self.handshake.extend_from_slice(fragment);
+            self.received.drain(..5 + length);
+        }
+    }
+
+    fn start(&mut self, session_id: &[u8], cipher_suites: &[u16]) {
+        self.hello_received = true;
+        self.received = Vec::new();
+        self.handshake = Vec::new();
+        self.sending.push(&server_hello(session_id, cipher_suites));
+    }
+}
+
+impl TarpitProtocol for Tls {
+    fn tick(&mut self, _treatment: &Treatment) -> Vec<u8> {
+        if !self.hello_received {
+            return Vec::new();
+        }
+
+        if self.sending.is_empty() {
+            self.sending.push(&certificate_header());
+            self.sending.push_random(CERTIFICATE_LENGTH);
+        }
+
+        let mut record = vec![CONTENT_TYPE_HANDSHAKE];
+
+        put_u16(&mut record, VERSION);
+        put_u16(&mut record, 1);
+        record.extend(self.sending.pop());
+
+        record
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.hello_received {
+            // they're waiting for us now
+            return Vec::new();
+        }
+
+        keep_up_to(&mut self.received, data, MAX_RECORD_LENGTH + 5);
+
+        if !self.read_records() {
+            let captures = vec![Capture::new("garbage", &self.received)];
+
+            self.start(&[], &[]);
+
+            return captures;
+        }
+
+        let mut handshake = self.handshake.as_slice();
+
+        let (Some(message_type), Some(length)) =
+            (read_u8(&mut handshake), read_u24(&mut handshake))
+        else {
+            return Vec::new();
+        };
+
+        let Some(body) = read_bytes(
+            &mut handshake,
+            usize::try_from(length).unwrap_or(usize::MAX),
+        ) else {
+            // wait for the rest
+            return Vec::new();
+        };
+
+        let hello = if message_type == CLIENT_HELLO {
+            parse_client_hello(body)
+        } else {
+            None
+        };
+
+        let Some(hello) = hello else {
+            let captures = vec![Capture::new("garbage", &self.handshake)];
+
+            self.start(&[], &[]);
+
+            return captures;
+        };
+
+        let mut captures = Vec::new();
+
+        if let Some(server_name) = hello.server_name.as_deref() {
+            captures.push(Capture::new("sni", server_name));
+        }
+
+        captures.push(Capture::new("ja3", hello.ja3.as_bytes()));
+        captures.push(Capture::new(
+            "ja3_hash",
+            format!("{:x}", md5::compute(&hello.ja3)).as_bytes(),
+        ));
+
+        self.start(&hello.session_id, &hello.cipher_suites);
+
+        captures
+    }
+}
+
+/// RFC 8701, these don't go into fingerprints.
+fn is_grease(value: u16) -> bool {
+    value & 0x0f0f == 0x0a0a && value >> 8 == value & 0xff
+}
+
+fn read_u16_list(mut data: &[u8]) -> Vec<u16> {
+    let mut values = Vec::new();
+
+    while let Some(value) = read_u16(&mut data) {
+        if !is_grease(value) {
+            values.push(value);
+        }
+    }
+
+    values
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// RFC 8446 4.1.2, though we only care about what's in the JA3 string
+/// (`version,ciphers,extensions,groups,point_formats`), and the server name.
+fn parse_client_hello(mut body: &[u8]) -> Option<ClientHello> {
+    let version = read_u16(&mut body)?;
+    let _random: &[u8] = read_bytes(&mut body, 32)?;
+
+    let session_id_length = read_u8(&mut body)?;
+    let session_id = read_bytes(&mut body, usize::from(session_id_length))?.to_vec();
+
+    let cipher_suites_length = read_u16(&mut body)?;
+    let cipher_suites = read_u16_list(read_bytes(&mut body, usize::from(cipher_suites_length))?);
+
+    let compression_methods_length = read_u8(&mut body)?;
+    let _compression_methods: &[u8] =
+        read_bytes(&mut body, usize::from(compression_methods_length))?;
+
+    let mut extension_types = Vec::new();
+    let mut groups = Vec::new();
+    let mut point_formats = Vec::new();
+    let mut server_name = None;
+
+    // extensions are optional
+    if let Some(extensions_length) = read_u16(&mut body) {
+        let mut extensions = read_bytes(&mut body, usize::from(extensions_length))?;
+
+        while !extensions.is_empty() {
+            let extension_type = read_u16(&mut extensions)?;
+            let length = read_u16(&mut extensions)?;
+            let mut extension = read_bytes(&mut extensions, usize::from(length))?;
+
+            if is_grease(extension_type) {
+                continue;
+            }
+
+            extension_types.push(extension_type);
+
+            match extension_type {
+                EXTENSION_SERVER_NAME => server_name = parse_server_name(extension),
+                EXTENSION_SUPPORTED_GROUPS => {
+                    let length = read_u16(&mut extension)?;
+
+                    groups = read_u16_list(read_bytes(&mut extension, usize::from(length))?);
+                },
+                EXTENSION_EC_POINT_FORMATS => {
+                    let length = read_u8(&mut extension)?;
+
+                    point_formats = read_bytes(&mut extension, usize::from(length))?.to_vec();
+                },
+                _ => {},
+            }
+        }
+    }
+
+    Some(ClientHello {
+        session_id,
+        ja3: format!(
+            "{},{},{},{},{}",
+            version,
+            join(&cipher_suites),
+            join(&extension_types),
+            join(&groups),
+            join(&point_formats)
+        ),
+        cipher_suites,
+        server_name,
+    })
+}
+
+/// RFC 6066 3, the first host name.
+fn parse_server_name(mut extension: &[u8]) -> Option<Vec<u8>> {
+    let length = read_u16(&mut extension)?;
+    let mut names = read_bytes(&mut extension, usize::from(length))?;
+
+    while !names.is_empty() {
+        let name_type = read_u8(&mut names)?;
+        let length = read_u16(&mut names)?;
+        let name = read_bytes(&mut names, usize::from(length))?;
+
+        if name_type == 0 {
+            return Some(name.to_vec());
+        }
+    }
+
+    None
+}
+
+/// A TLS 1.2 `ServerHello`, picking the first TLS 1.2 suite the client offers.
+fn server_hello(session_id: &[u8], cipher_suites: &[u16]) -> Vec<u8> {
+    let mut random = [0_u8; 32];
+    with_rng(|rng| rng.fill(&mut random));
+
+    // TLS 1.3 suites need a TLS 1.3 `ServerHello`, which needs a key share
+    let cipher_suite = cipher_suites
+        .iter()
+        .copied()
+        .find(|suite| !(0x1301..=0x1305).contains(suite))
+        .unwrap_or(FALLBACK_CIPHER_SUITE);
+
+    let mut body = Vec::new();
+
+    put_u16(&mut body, VERSION);
+    body.extend_from_slice(&random);
+    body.push(u8::try_from(session_id.len()).expect("Session IDs are short"));
+    body.extend_from_slice(session_id);
+    put_u16(&mut body, cipher_suite);
+    // no compression
+    body.push(0);
+    // no extensions
+    put_u16(&mut body, 0);
+
+    let mut message = vec![SERVER_HELLO];
+
+    put_u24(
+        &mut message,
+        u32::try_from(body.len()).expect("Our hello is short"),
+    );
+    message.extend_from_slice(&body);
+
+    message
+}
+
+/// RFC 5246 7.4.2, the lengths of a `Certificate` with a single certificate. The certificate
+/// itself follows, byte by byte.
+fn certificate_header() -> Vec<u8> {
+    let mut header = vec![CERTIFICATE];
+
+    put_u24(&mut header, CERTIFICATE_LENGTH + 6);
+    put_u24(&mut header, CERTIFICATE_LENGTH + 3);
+    put_u24(&mut header, CERTIFICATE_LENGTH);
+
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::tls::{CERTIFICATE, SERVER_HELLO, Tls};
+    use crate::protocol::wire::{put_u16, put_u24};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    fn extension(buffer: &mut Vec<u8>, extension_type: u16, data: &[u8]) {
+        put_u16(buffer, extension_type);
+        put_u16(buffer, u16::try_from(data.len()).unwrap());
+        buffer.extend_from_slice(data);
+    }
+
+    /// The handshake message, padded by `padding` bytes (RFC 7685), none or at least 4.
+    fn handshake(padding: usize) -> Vec<u8> {
+        let mut body = Vec::new();
+
+        put_u16(&mut body, 0x0303);
+        body.extend_from_slice(&[7; 32]);
+        // session ID
+        body.extend_from_slice(&[2, 0xab, 0xcd]);
+        // cipher suites, with GREASE
+        body.extend_from_slice(&[0, 8, 0x3a, 0x3a, 0x13, 0x01, 0xc0, 0x2b, 0xc0, 0x2f]);
+        // compression
+        body.extend_from_slice(&[1, 0]);
+
+        let mut extensions = Vec::new();
+
+        extension(&mut extensions, 0x2a2a, &[]);
+        extension(
+            &mut extensions,
+            0,
+            &[
+                0, 14, 0, 0, 11, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'o', b'r', b'g',
+            ],
+        );
+        extension(&mut extensions, 10, &[0, 4, 0, 29, 0, 23]);
+        extension(&mut extensions, 11, &[1, 0]);
+        extension(&mut extensions, 16, &[0, 3, 2, b'h', b'2']);
+
+        if padding > 0 {
+            extension(&mut extensions, 21, &vec![0; padding - 4]);
+        }
+
+        put_u16(&mut body, u16::try_from(extensions.len()).unwrap());
+        body.extend_from_slice(&extensions);
+
+        let mut handshake = vec![1];
+        put_u24(&mut handshake, u32::try_from(body.len()).unwrap());
+        handshake.extend_from_slice(&body);
+
+        handshake
+    }
+
+    fn client_hello() -> Vec<u8> {
+        let handshake = handshake(0);
+
+        // split over 2 records
+        let (first, second) = handshake.split_at(20);
+
+        let mut records = Vec::new();
+
+        for fragment in [first, second] {
+            records.extend_from_slice(&[22, 3, 1]);
+            put_u16(&mut records, u16::try_from(fragment.len()).unwrap());
+            records.extend_from_slice(fragment);
+        }
+
+        records
+    }
+
+    #[test]
+    fn reads_full_records_in_parts() {
+        let unpadded = handshake(0).len();
+
+        let mut records = vec![22, 3, 1];
+        put_u16(&mut records, 1 << 14);
+        records.extend_from_slice(&handshake((1 << 14) - unpadded));
+        // what comes after doesn't fit anymore
+        records.extend_from_slice(&[0; 100]);
+
+        let mut tls = Tls::default();
+
+        let captures = records
+            .chunks(1024)
+            .flat_map(|chunk| tls.receive(chunk))
+            .collect::<Vec<_>>();
+
+        assert_eq!(captures[0], Capture::new("sni", b"example.org"));
+    }
+
+    #[test]
+    fn fingerprints_and_drips() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Tls, false);
+
+        let mut tls = Tls::default();
+
+        assert_eq!(tls.tick(&treatment), b"", "Waits for the client");
+
+        let hello = client_hello();
+
+        assert_eq!(tls.receive(&hello[..30]), [], "Waits for the whole hello");
+        assert_eq!(
+            tls.receive(&hello[30..]),
+            [
+                Capture::new("sni", b"example.org"),
+                Capture::new("ja3", b"771,4865-49195-49199,0-10-11-16,29-23,0"),
+                Capture::new("ja3_hash", b"b8a9d018726f8d9d86a906c6ea2dc7cd"),
+            ]
+        );
+
+        let mut handshake = Vec::new();
+
+        for _ in 0..100 {
+            let record = tls.tick(&treatment);
+
+            assert_eq!(record[..5], [22, 3, 3, 0, 1], "A single byte record");
+
+            handshake.push(record[5]);
+        }
+
+        assert_eq!(handshake[0], SERVER_HELLO);
+        // length, version, random, then the session ID and the suite
+        assert_eq!(handshake[38..44], [2, 0xab, 0xcd, 0xc0, 0x2b, 0]);
+
+        let certificate = &handshake[4 + usize::from(handshake[3])..];
+
+        assert_eq!(certificate[0], CERTIFICATE);
+    }
+
+    #[test]
+    fn starts_anyway_on_garbage() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Tls, false);
+
+        let mut tls = Tls::default();
+
+        assert_eq!(
+            tls.receive(b"GET / HTTP/1.1\r\n"),
+            [Capture::new("garbage", b"GET / HTTP/1.1\r\n")]
+        );
+        assert_eq!(tls.tick(&treatment), [22, 3, 3, 0, 1, SERVER_HELLO]);
+    }
+}
@@ -0,0 +1,254 @@
+use std::fmt::Write as _;
+
+use rand::RngExt as _;
+
+use crate::protocol::wire::{put_u32, read_bytes};
+use crate::protocol::{Capture, Drip, TarpitProtocol, keep_up_to};
+use crate::random::with_rng;
+use crate::rules::Treatment;
+
+/// RFC 6143 7.1.1.
+const VERSION: &[u8] = b"RFB 003.008\n";
+const VERSION_LENGTH: usize = 12;
+
+/// RFC 6143 7.1.2 and 7.2.2.
+const VNC_AUTHENTICATION: u8 = 2;
+const CHALLENGE_LENGTH: usize = 16;
+
+/// RFC 6143 7.1.3, failed, with a reason that the client waits for in full.
+const FAILED: u32 = 1;
+const REASON_LENGTH: u32 = 0xffff;
+const REASON: &[u8] = b"Authentication failure, too many attempts. Please try again later.";
+
+/// Drips the version, offers VNC authentication and drips its challenge. The client's response is
+/// captured with the challenge, so its password can be found in the lists being tried.
+#[derive(Debug, Default)]
+pub struct Vnc {
+    received: Vec<u8>,
+    stage: Stage,
+    /// Only the older clients don't get to choose.
+    minor_version: u8,
+    challenge: [u8; CHALLENGE_LENGTH],
+    sending: Drip,
+}
+
+#[derive(Debug, Default, Eq, PartialEq)]
+enum Stage {
+    #[default]
+    Version,
+    SecurityType,
+    Response,
+    Done,
+}
+
+impl Vnc {
+    fn send_challenge(&mut self) {
+        with_rng(|rng| rng.fill(&mut self.challenge));
+
+        self.sending.push(&self.challenge);
+        self.stage = Stage::Response;
+    }
+
+    /// Handles what the current stage needs, if it's all there.
+    fn receive_stage(&mut self) -> Option<Vec<Capture>> {
+        // at most a few bytes, and the stages need `self`
+        let owned = self.received.clone();
+        let mut received = owned.as_slice();
+
+        let captures = match self.stage {
+            Stage::Version => {
+                let version = read_bytes(&mut received, VERSION_LENGTH)?;
+
+                let capture = Capture::new("client_version", version.trim_ascii_end());
+
+                self.minor_version = version
+                    .strip_prefix(b"RFB 003.")
+                    .and_then(|minor| std::str::from_utf8(minor.trim_ascii_end()).ok())
+                    .and_then(|minor| minor.parse().ok())
+                    .unwrap_or(8);
+
+                if self.minor_version >= 7 {
+                    self.sending.push(&[1, VNC_AUTHENTICATION]);
+                    self.stage = Stage::SecurityType;
+                } else {
+                    // RFC 6143 Appendix A.1, the server decides
+                    let mut security_type = Vec::new();
+                    put_u32(&mut security_type, u32::from(VNC_AUTHENTICATION));
+
+                    self.sending.push(&security_type);
+                    self.send_challenge();
+                }
+
+                vec![capture]
+            },
+            Stage::SecurityType => {
+                let _security_type: &[u8] = read_bytes(&mut received, 1)?;
+
+                self.send_challenge();
+
+                Vec::new()
+            },
+            Stage::Response => {
+                let response = read_bytes(&mut received, CHALLENGE_LENGTH)?;
+
+                let capture = Capture::new(
+                    "challenge_response",
+                    format!("{}:{}", hex(&self.challenge), hex(response)).as_bytes(),
+                );
+
+                let mut result = Vec::new();
+                put_u32(&mut result, FAILED);
+
+                // older clients don't get a reason
+                if self.minor_version >= 8 {
+                    put_u32(&mut result, REASON_LENGTH);
+
+                    self.sending.push(&result);
+                    self.sending.push(REASON);
+                    self.sending.push_random(
+                        REASON_LENGTH - u32::try_from(REASON.len()).expect("Our reason is short"),
+                    );
+                } else {
+                    self.sending.push(&result);
+                }
+
+                self.stage = Stage::Done;
+
+                vec![capture]
+            },
+            Stage::Done => return None,
+        };
+
+        let consumed = self.received.len() - received.len();
+        self.received.drain(..consumed);
+
+        Some(captures)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").unwrap();
+
+        hex
+    })
+}
+
+impl TarpitProtocol for Vnc {
+    fn setup(&mut self) -> Vec<u8> {
+        // the server goes first, but slowly
+        self.sending.push(VERSION);
+
+        Vec::new()
+    }
+
+    fn tick(&mut self, _treatment: &Treatment) -> Vec<u8> {
+        self.sending.pop()
+    }
+
+    fn receive(&mut self, data: &[u8]) -> Vec<Capture> {
+        if self.stage == Stage::Done {
+            return Vec::new();
+        }
+
+        // more than any stage needs
+        keep_up_to(
+            &mut self.received,
+            data,
+            VERSION_LENGTH + 1 + CHALLENGE_LENGTH,
+        );
+
+        let mut captures = Vec::new();
+
+        while let Some(stage_captures) = self.receive_stage() {
+            captures.extend(stage_captures);
+        }
+
+        captures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::config::Config;
+    use crate::protocol::vnc::{Vnc, hex};
+    use crate::protocol::{Capture, Protocol, TarpitProtocol as _};
+    use crate::rules::Treatment;
+
+    #[test]
+    fn captures_response() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Vnc, false);
+
+        let mut vnc = Vnc::default();
+
+        assert_eq!(vnc.setup(), b"");
+
+        for &byte in b"RFB 003.008\n" {
+            assert_eq!(vnc.tick(&treatment), [byte], "One byte per tick");
+        }
+
+        assert_eq!(vnc.tick(&treatment), b"", "Waits for the client");
+        assert_eq!(
+            vnc.receive(b"RFB 003.008\n"),
+            [Capture::new("client_version", b"RFB 003.008")]
+        );
+        assert_eq!(vnc.tick(&treatment), [1]);
+        assert_eq!(vnc.tick(&treatment), [2], "VNC authentication");
+        assert_eq!(vnc.receive(&[2]), []);
+
+        let challenge = (0..16)
+            .flat_map(|_| vnc.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(vnc.tick(&treatment), b"", "Waits for the response");
+        assert_eq!(
+            vnc.receive(&[0xab; 16]),
+            [Capture::new(
+                "challenge_response",
+                format!("{}:{}", hex(&challenge), "ab".repeat(16)).as_bytes()
+            )]
+        );
+
+        let result = (0..8)
+            .flat_map(|_| vnc.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(result, [0, 0, 0, 1, 0, 0, 0xff, 0xff], "Failed, slowly");
+    }
+
+    #[test]
+    fn keeps_what_fits_of_long_reads() {
+        let mut vnc = Vnc::default();
+
+        // version, security type and response at once, and more
+        let captures = vnc.receive(&[b"RFB 003.008\n".as_slice(), &[2], &[0; 100]].concat());
+
+        assert_eq!(
+            captures
+                .iter()
+                .map(|capture| capture.kind)
+                .collect::<Vec<_>>(),
+            ["client_version", "challenge_response"]
+        );
+    }
+
+    #[test]
+    fn decides_for_older_clients() {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Vnc, false);
+
+        let mut vnc = Vnc::default();
+
+        assert_eq!(
+            vnc.receive(b"RFB 003.003\n"),
+            [Capture::new("client_version", b"RFB 003.003")]
+        );
+
+        let security_type = (0..4)
+            .flat_map(|_| vnc.tick(&treatment))
+            .collect::<Vec<_>>();
+
+        assert_eq!(security_type, [0, 0, 0, 2]);
+    }
+}
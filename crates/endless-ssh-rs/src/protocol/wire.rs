@@ -0,0 +1,168 @@
+//! Network byte order, for the binary protocols, unless the name ends in `_le`. The readers advance
+//! `data` past what they read, and return `None` when there isn't enough of it.
+
+pub fn read_u8(data: &mut &[u8]) -> Option<u8> {
+    let (&value, rest) = data.split_first()?;
+
+    *data = rest;
+
+    Some(value)
+}
+
+#[expect(clippy::big_endian_bytes, reason = "Network byte order")]
+pub fn read_u16(data: &mut &[u8]) -> Option<u16> {
+    let (&bytes, rest) = data.split_first_chunk::<2>()?;
+
+    *data = rest;
+
+    Some(u16::from_be_bytes(bytes))
+}
+
+#[expect(clippy::big_endian_bytes, reason = "Network byte order")]
+pub fn read_u24(data: &mut &[u8]) -> Option<u32> {
+    let (&[high, middle, low], rest) = data.split_first_chunk::<3>()?;
+
+    *data = rest;
+
+    Some(u32::from_be_bytes([0, high, middle, low]))
+}
+
+#[expect(clippy::big_endian_bytes, reason = "Network byte order")]
+pub fn read_u32(data: &mut &[u8]) -> Option<u32> {
+    let (&bytes, rest) = data.split_first_chunk::<4>()?;
+
+    *data = rest;
+
+    Some(u32::from_be_bytes(bytes))
+}
+
+pub fn read_bytes<'d>(data: &mut &'d [u8], length: usize) -> Option<&'d [u8]> {
+    let (bytes, rest) = data.split_at_checked(length)?;
+
+    *data = rest;
+
+    Some(bytes)
+}
+
+#[expect(clippy::big_endian_bytes, reason = "Network byte order")]
+pub fn put_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Only the lower 24 bits of `value` are written.
+#[expect(clippy::big_endian_bytes, reason = "Network byte order")]
+pub fn put_u24(buffer: &mut Vec<u8>, value: u32) {
+    let [_, high, middle, low] = value.to_be_bytes();
+
+    buffer.extend_from_slice(&[high, middle, low]);
+}
+
+#[expect(clippy::big_endian_bytes, reason = "Network byte order")]
+pub fn put_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+#[expect(clippy::little_endian_bytes, reason = "MySQL and RDP")]
+pub fn read_u16_le(data: &mut &[u8]) -> Option<u16> {
+    let (&bytes, rest) = data.split_first_chunk::<2>()?;
+
+    *data = rest;
+
+    Some(u16::from_le_bytes(bytes))
+}
+
+#[expect(clippy::little_endian_bytes, reason = "MySQL and RDP")]
+pub fn read_u24_le(data: &mut &[u8]) -> Option<u32> {
+    let (&[low, middle, high], rest) = data.split_first_chunk::<3>()?;
+
+    *data = rest;
+
+    Some(u32::from_le_bytes([low, middle, high, 0]))
+}
+
+#[expect(clippy::little_endian_bytes, reason = "MySQL and RDP")]
+pub fn read_u32_le(data: &mut &[u8]) -> Option<u32> {
+    let (&bytes, rest) = data.split_first_chunk::<4>()?;
+
+    *data = rest;
+
+    Some(u32::from_le_bytes(bytes))
+}
+
+#[expect(clippy::little_endian_bytes, reason = "MySQL and RDP")]
+pub fn put_u16_le(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Only the lower 24 bits of `value` are written.
+#[expect(clippy::little_endian_bytes, reason = "MySQL and RDP")]
+pub fn put_u24_le(buffer: &mut Vec<u8>, value: u32) {
+    let [low, middle, high, _] = value.to_le_bytes();
+
+    buffer.extend_from_slice(&[low, middle, high]);
+}
+
+#[expect(clippy::little_endian_bytes, reason = "MySQL and RDP")]
+pub fn put_u32_le(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Up to the first NUL, which is skipped.
+pub fn read_c_string<'d>(data: &mut &'d [u8]) -> Option<&'d [u8]> {
+    let end = data.iter().position(|&byte| byte == 0)?;
+
+    let string = read_bytes(data, end)?;
+
+    *data = data.get(1..)?;
+
+    Some(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::protocol::wire::{
+        put_u16, put_u16_le, put_u24, put_u24_le, put_u32, put_u32_le, read_bytes, read_c_string,
+        read_u16, read_u16_le, read_u24, read_u24_le, read_u32, read_u32_le,
+    };
+
+    #[test]
+    fn round_trips() {
+        let mut buffer = Vec::new();
+
+        put_u16(&mut buffer, 0x0303);
+        put_u24(&mut buffer, 0x01_02_03);
+        put_u32(&mut buffer, 35000);
+        buffer.push(7);
+
+        let mut data = buffer.as_slice();
+
+        assert_eq!(read_u16(&mut data), Some(0x0303));
+        assert_eq!(read_u24(&mut data), Some(0x01_02_03));
+        assert_eq!(read_u32(&mut data), Some(35000));
+        assert_eq!(read_bytes(&mut data, 2), None, "Not enough");
+        assert_eq!(read_bytes(&mut data, 1), Some([7].as_slice()));
+        assert_eq!(read_u16(&mut data), None, "Empty");
+    }
+
+    #[test]
+    fn round_trips_little_endian() {
+        let mut buffer = Vec::new();
+
+        put_u16_le(&mut buffer, 0x0102);
+        put_u24_le(&mut buffer, 0x01_02_03);
+        put_u32_le(&mut buffer, 0x0102_0304);
+        buffer.extend_from_slice(b"root\0");
+
+        assert_eq!(buffer[..3], [2, 1, 3]);
+
+        let mut data = buffer.as_slice();
+
+        assert_eq!(read_u16_le(&mut data), Some(0x0102));
+        assert_eq!(read_u24_le(&mut data), Some(0x01_02_03));
+        assert_eq!(read_u32_le(&mut data), Some(0x0102_0304));
+        assert_eq!(read_c_string(&mut data), Some(b"root".as_slice()));
+        assert_eq!(read_c_string(&mut data), None, "Empty");
+    }
+}
@@ -0,0 +1,107 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rand::SeedableRng as _;
+use rand::rngs::StdRng;
+
+/// Numbers the sessions, so together with the seed each one has its own stream.
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// The running session's, swapped in by `in_session`.
+    static CURRENT: RefCell<StdRng> = RefCell::new(rand::make_rng());
+}
+
+pub fn next_session_id() -> u64 {
+    NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// With a seed, the stream only depends on it and the session id, so the session can be replayed.
+/// `StdRng` is only reproducible with the same version of `rand`, which we pin.
+pub fn session_rng(seed: Option<u64>, session_id: u64) -> StdRng {
+    let Some(seed) = seed else {
+        return rand::make_rng();
+    };
+
+    let mut key = [0; 32];
+
+    let (seed_bytes, rest) = key.split_at_mut(8);
+    seed_bytes.copy_from_slice(&to_bytes(seed));
+    rest[..8].copy_from_slice(&to_bytes(session_id));
+
+    StdRng::from_seed(key)
+}
+
+#[expect(
+    clippy::little_endian_bytes,
+    reason = "Any order, as long as it's fixed"
+)]
+fn to_bytes(value: u64) -> [u8; 8] {
+    value.to_le_bytes()
+}
+
+/// Runs `f` with `rng` as what `with_rng` hands out. Everything random a session does happens in
+/// here, so it all comes from its own stream.
+pub fn in_session<T, F: FnOnce() -> T>(rng: &mut StdRng, f: F) -> T {
+    CURRENT.with_borrow_mut(|current| std::mem::swap(current, rng));
+
+    let result = f();
+
+    CURRENT.with_borrow_mut(|current| std::mem::swap(current, rng));
+
+    result
+}
+
+/// The running session's generator, or the thread's own outside of a session. Don't nest.
+pub fn with_rng<T, F: FnOnce(&mut StdRng) -> T>(f: F) -> T {
+    CURRENT.with_borrow_mut(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::{assert_eq, assert_ne};
+    use rand::RngExt;
+
+    use crate::config::Config;
+    use crate::protocol::Protocol;
+    use crate::random::{in_session, session_rng, with_rng};
+    use crate::rules::Treatment;
+
+    fn replay(seed: Option<u64>, session_id: u64) -> Vec<u8> {
+        let treatment = Treatment::defaults(&Config::default(), Protocol::Ssh, false);
+
+        let mut rng = session_rng(seed, session_id);
+        let mut session = Protocol::Ssh.session();
+
+        std::iter::repeat_with(|| in_session(&mut rng, || session.tick(&treatment)))
+            .take(10)
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn replays_sessions() {
+        assert_eq!(replay(Some(42), 7), replay(Some(42), 7), "Same session");
+        assert_ne!(replay(Some(42), 7), replay(Some(42), 8), "Other session");
+        assert_ne!(replay(Some(42), 7), replay(Some(43), 7), "Other seed");
+        assert_ne!(replay(None, 7), replay(None, 7), "No seed");
+    }
+
+    #[test]
+    fn sessions_are_isolated() {
+        let mut rng = session_rng(Some(1), 1);
+
+        let inside = in_session(&mut rng, || with_rng(RngExt::random::<u64>));
+        let outside = with_rng(RngExt::random::<u64>);
+
+        let mut expected = session_rng(Some(1), 1);
+
+        assert_eq!(inside, expected.random::<u64>());
+        assert_ne!(outside, inside, "The thread's own");
+        assert_eq!(
+            in_session(&mut rng, || with_rng(RngExt::random::<u64>)),
+            expected.random::<u64>(),
+            "Continues where it left off"
+        );
+    }
+}
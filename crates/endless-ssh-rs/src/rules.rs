@@ -0,0 +1,289 @@
+use std::net::IpAddr;
+use std::num::NonZeroU16;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use time::OffsetDateTime;
+
+use crate::cidr::Cidr;
+use crate::config::Config;
+use crate::delay::DelayStrategy;
+use crate::line::shape::{Alphabet, LineLength};
+use crate::line::{Corpus, CorpusOrder, LineGenerator};
+use crate::protocol::Protocol;
+use crate::time_window::TimeWindow;
+
+/// A rule from the config file. The first rule whose conditions all match a new connection
+/// decides how it is treated. Conditions that are left out always match.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    /// Source address ranges, any of them.
+    #[serde(default)]
+    pub sources: Vec<Cidr>,
+    /// Local ports, any of them.
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    /// Time windows, any of them.
+    #[serde(default)]
+    pub times: Vec<TimeWindow>,
+    pub repeat_offender: Option<bool>,
+    #[serde(default)]
+    pub action: Action,
+}
+
+/// Overrides of the defaults from the command line.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Action {
+    /// In milliseconds, `10000` or `5000-20000`.
+    pub delay: Option<DelayStrategy>,
+    /// What we pretend to be, instead of the listener's protocol.
+    pub protocol: Option<Protocol>,
+    pub line_generator: Option<LineGenerator>,
+    pub max_line_length: Option<NonZeroU16>,
+    /// Of the random lines, `uniform`, `fixed`, `normal:40:10` or `zipf:1.5`.
+    pub line_length: Option<LineLength>,
+    /// Of the random lines, `printable`, `alphanumeric`, `hex`, `base64` or `custom:abc`.
+    pub alphabet: Option<Alphabet>,
+    /// After this, the client is released.
+    pub lifetime_seconds: Option<u64>,
+    /// Prioritized clients may use the reserved slots and are exempt from the per-IP cap.
+    pub priority: Option<bool>,
+    /// Varies everything a scanner could fingerprint, per connection.
+    pub disguise: Option<bool>,
+}
+
+/// What we know about a new connection when choosing its treatment.
+pub struct Connection {
+    pub ip: IpAddr,
+    pub local_port: u16,
+    pub at: OffsetDateTime,
+    pub repeat_offender: bool,
+}
+
+impl Rule {
+    fn matches(&self, connection: &Connection) -> bool {
+        (self.sources.is_empty()
+            || self
+                .sources
+                .iter()
+                .any(|source| source.contains(connection.ip)))
+            && (self.ports.is_empty() || self.ports.contains(&connection.local_port))
+            && (self.times.is_empty() || self.times.iter().any(|time| time.contains(connection.at)))
+            && self
+                .repeat_offender
+                .is_none_or(|repeat_offender| repeat_offender == connection.repeat_offender)
+    }
+}
+
+pub fn find_rule<'r>(rules: &'r [Rule], connection: &Connection) -> Option<&'r Rule> {
+    rules.iter().find(|rule| rule.matches(connection))
+}
+
+/// How a client is treated for as long as it is in the tarpit.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Treatment {
+    /// Name of the rule that matched, if any.
+    pub rule: Option<String>,
+    /// From the listener, unless a rule says otherwise.
+    pub protocol: Protocol,
+    pub delay: DelayStrategy,
+    pub line_generator: LineGenerator,
+    /// For the corpus line generator.
+    pub corpus: Option<Arc<Corpus>>,
+    /// Of the corpus lines this client hasn't been sent yet.
+    pub corpus_order: CorpusOrder,
+    pub max_line_length: NonZeroU16,
+    pub line_length: LineLength,
+    pub alphabet: Alphabet,
+    pub lifetime: Option<Duration>,
+    pub priority: bool,
+    /// See `disguise::start`.
+    pub disguise: bool,
+    /// Lines the SSH banner sends first, without CR LF.
+    pub pre_banner: &'static [&'static str],
+}
+
+impl Treatment {
+    /// The treatment from the command line options, for when no rule matches.
+    pub fn defaults(config: &Config, protocol: Protocol, repeat_offender: bool) -> Self {
+        let delay = if repeat_offender {
+            config.repeat_offender_delay.unwrap_or(config.delay)
+        } else {
+            config.delay
+        };
+
+        Self {
+            rule: None,
+            protocol,
+            delay: DelayStrategy::Fixed(delay),
+            line_generator: config.line_generator,
+            corpus: config.corpus.clone(),
+            corpus_order: CorpusOrder::default(),
+            max_line_length: config.max_line_length,
+            line_length: config.line_length,
+            alphabet: config.alphabet.clone(),
+            lifetime: None,
+            // repeat offenders deserve our full attention
+            priority: repeat_offender,
+            disguise: config.disguise,
+            pre_banner: &[],
+        }
+    }
+
+    /// A line from the line generator, at most `max_length` long, ending in CR LF.
+    pub fn line(&self, max_length: usize) -> Vec<u8> {
+        self.line_generator.line(
+            self.corpus.as_deref(),
+            &self.corpus_order,
+            self.line_length,
+            &self.alphabet,
+            max_length,
+        )
+    }
+
+    pub fn apply(&mut self, rule: &Rule) {
+        let action = &rule.action;
+
+        self.rule = Some(rule.name.clone());
+
+        if let Some(delay) = action.delay {
+            self.delay = delay;
+        }
+
+        if let Some(protocol) = action.protocol {
+            self.protocol = protocol;
+        }
+
+        if let Some(line_generator) = action.line_generator {
+            self.line_generator = line_generator;
+        }
+
+        if let Some(max_line_length) = action.max_line_length {
+            self.max_line_length = max_line_length;
+        }
+
+        if let Some(line_length) = action.line_length {
+            self.line_length = line_length;
+        }
+
+        if let Some(ref alphabet) = action.alphabet {
+            self.alphabet = alphabet.clone();
+        }
+
+        if let Some(lifetime_seconds) = action.lifetime_seconds {
+            self.lifetime = Some(Duration::from_secs(lifetime_seconds));
+        }
+
+        if let Some(priority) = action.priority {
+            self.priority = priority;
+        }
+
+        if let Some(disguise) = action.disguise {
+            self.disguise = disguise;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+    use time::macros::datetime;
+
+    use crate::config::{Config, ConfigFile};
+    use crate::delay::DelayStrategy;
+    use crate::line::shape::{Alphabet, LineLength};
+    use crate::protocol::Protocol;
+    use crate::rules::{Connection, Treatment, find_rule};
+
+    const RULES: &str = r#"
+        [[rules]]
+        name = "known-scanners"
+        sources = ["192.0.2.0/24", "2001:db8::/32"]
+        action = { delay = "30000", protocol = "http", lifetime_seconds = 3600 }
+
+        [[rules]]
+        name = "night-shift-regulars"
+        ports = [22]
+        times = ["Mon-Fri 18:00-08:00", "Sat,Sun"]
+        repeat_offender = true
+        action = { delay = "20000-40000", max_line_length = 1000, line_length = "zipf:1.5", alphabet = "hex", priority = false }
+    "#;
+
+    fn connection_factory(ip: &str, repeat_offender: bool) -> Connection {
+        Connection {
+            ip: ip.parse().unwrap(),
+            local_port: 22,
+            // a Saturday
+            at: datetime!(2026-10-17 12:00 UTC),
+            repeat_offender,
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let config_file = ConfigFile::parse(RULES).unwrap();
+
+        let scanner = connection_factory("::ffff:192.0.2.10", true);
+
+        assert_eq!(
+            find_rule(&config_file.rules, &scanner).map(|rule| rule.name.as_str()),
+            Some("known-scanners")
+        );
+
+        let regular = connection_factory("198.51.100.1", true);
+
+        assert_eq!(
+            find_rule(&config_file.rules, &regular).map(|rule| rule.name.as_str()),
+            Some("night-shift-regulars")
+        );
+
+        let newcomer = connection_factory("198.51.100.1", false);
+
+        assert_eq!(find_rule(&config_file.rules, &newcomer), None);
+    }
+
+    #[test]
+    fn rule_overrides_defaults() {
+        let config_file = ConfigFile::parse(RULES).unwrap();
+
+        let config = Config::default();
+
+        let mut treatment = Treatment::defaults(&config, Protocol::Ssh, true);
+
+        assert!(treatment.priority, "Repeat offenders have priority");
+
+        treatment.apply(&config_file.rules[1]);
+
+        assert_eq!(treatment.rule.as_deref(), Some("night-shift-regulars"));
+        assert_eq!(
+            treatment.delay,
+            DelayStrategy::Uniform(Duration::from_secs(20), Duration::from_secs(40))
+        );
+        assert_eq!(treatment.max_line_length.get(), 1000);
+        assert_eq!(treatment.line_length, LineLength::Zipf { exponent: 1.5 });
+        assert_eq!(treatment.alphabet, Alphabet::Hex);
+        assert_eq!(treatment.lifetime, None);
+        assert!(!treatment.priority, "Rule takes away priority");
+    }
+
+    #[test]
+    fn rule_overrides_protocol() {
+        let config_file = ConfigFile::parse(RULES).unwrap();
+
+        let mut treatment = Treatment::defaults(&Config::default(), Protocol::Ssh, false);
+
+        treatment.apply(&config_file.rules[1]);
+
+        assert_eq!(treatment.protocol, Protocol::Ssh, "Listener's protocol");
+
+        treatment.apply(&config_file.rules[0]);
+
+        assert_eq!(treatment.protocol, Protocol::Http);
+    }
+}
@@ -0,0 +1,277 @@
+use std::net::SocketAddr;
+
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+use tracing::{Level, event};
+
+use crate::statistics::StatisticsMessage;
+use crate::time_window::TimeWindow;
+
+type StdDuration = std::time::Duration;
+
+const CHECK_INTERVAL: StdDuration = StdDuration::from_secs(15);
+
+/// How long the fallback target gets to accept a forwarded connection.
+const FORWARD_CONNECT_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+/// After this, a forwarded connection is closed, whatever is going on.
+const FORWARD_LIFETIME: StdDuration = StdDuration::from_hours(1);
+
+/// When the tarpit traps new clients. Outside of the windows new clients get the fallback.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    /// Active during any of these.
+    pub active: Vec<TimeWindow>,
+    #[serde(default)]
+    pub fallback: Fallback,
+    #[serde(default)]
+    pub on_close: OnClose,
+}
+
+/// What happens to new clients while the tarpit is inactive.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fallback {
+    /// Close the connection right away.
+    #[default]
+    Close,
+    /// Pass the connection on to a real service.
+    Forward(SocketAddr),
+}
+
+/// What happens to trapped clients when a window closes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnClose {
+    /// Keep them until they leave.
+    #[default]
+    Keep,
+    /// Let them go.
+    Release,
+}
+
+impl Schedule {
+    pub fn is_active(&self, at: OffsetDateTime) -> bool {
+        self.active.iter().any(|window| window.contains(at))
+    }
+}
+
+impl std::fmt::Display for Fallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Fallback::Close => write!(f, "close"),
+            Fallback::Forward(target) => write!(f, "forward to {}", target),
+        }
+    }
+}
+
+/// Checks the schedule and publishes whether the tarpit is active, logging each transition.
+pub async fn watch_schedule(
+    cancellation_token: CancellationToken,
+    schedule: Schedule,
+    active_sender: watch::Sender<bool>,
+    statistics_sender: UnboundedSender<StatisticsMessage>,
+) {
+    let _guard = cancellation_token.clone().drop_guard();
+
+    let mut check_interval = tokio::time::interval(CHECK_INTERVAL);
+    check_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            () = cancellation_token.cancelled() => {
+                break;
+            },
+            _ = check_interval.tick() => {
+                let active = schedule.is_active(OffsetDateTime::now_utc());
+
+                let changed = active_sender.send_if_modified(|current| {
+                    std::mem::replace(current, active) != active
+                });
+
+                if changed {
+                    if active {
+                        event!(Level::INFO, "Schedule window opened, trapping new clients");
+                    } else {
+                        event!(
+                            Level::INFO,
+                            fallback = %schedule.fallback,
+                            on_close = ?schedule.on_close,
+                            "Schedule window closed, no longer trapping new clients"
+                        );
+                    }
+
+                    statistics_sender
+                        .send(StatisticsMessage::ScheduleTransition(active))
+                        .expect("Channel should always exist");
+                }
+            },
+        }
+    }
+}
+
+/// Waits until the tarpit becomes active or inactive. Never completes when there is no schedule
+/// (anymore).
+pub async fn wait_for_transition(active_receiver: &mut watch::Receiver<bool>) -> bool {
+    if active_receiver.changed().await.is_err() {
+        // nobody is going to change it anymore
+        return std::future::pending().await;
+    }
+
+    *active_receiver.borrow_and_update()
+}
+
+/// Passes `socket` on to `target`, for as long as both ends want, up to `FORWARD_LIFETIME`, or
+/// until we shut down.
+pub async fn forward(
+    mut socket: TcpStream,
+    addr: SocketAddr,
+    target: SocketAddr,
+    cancellation_token: CancellationToken,
+) {
+    let upstream = tokio::time::timeout(FORWARD_CONNECT_TIMEOUT, TcpStream::connect(target));
+
+    let mut upstream = match cancellation_token.run_until_cancelled(upstream).await {
+        None => return,
+        Some(Ok(Ok(upstream))) => upstream,
+        Some(Ok(Err(error))) => {
+            event!(Level::WARN, ?error, ?addr, %target, "Failed to connect to fallback");
+
+            return;
+        },
+        Some(Err(_)) => {
+            event!(Level::WARN, ?addr, %target, "Timed out connecting to fallback");
+
+            return;
+        },
+    };
+
+    let copy = tokio::time::timeout(
+        FORWARD_LIFETIME,
+        tokio::io::copy_bidirectional(&mut socket, &mut upstream),
+    );
+
+    match cancellation_token.run_until_cancelled(copy).await {
+        None => {
+            event!(Level::DEBUG, ?addr, %target, "Forwarded connection closed on shutdown");
+        },
+        Some(Ok(Ok((to_target, to_client)))) => {
+            event!(
+                Level::DEBUG,
+                ?addr,
+                %target,
+                to_target,
+                to_client,
+                "Forwarded connection closed"
+            );
+        },
+        Some(Ok(Err(error))) => {
+            event!(Level::DEBUG, ?error, ?addr, %target, "Forwarded connection failed");
+        },
+        Some(Err(_)) => {
+            event!(Level::DEBUG, ?addr, %target, "Forwarded connection timed out");
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+    use time::macros::datetime;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::sync::CancellationToken;
+
+    use crate::config::ConfigFile;
+    use crate::schedule::{Fallback, OnClose, forward};
+
+    #[test]
+    fn parses_schedule() {
+        let config_file = ConfigFile::parse(
+            r#"
+            [schedule]
+            active = ["Mon-Fri 18:00-08:00", "Sat,Sun"]
+            fallback = { forward = "127.0.0.1:22" }
+            on_close = "release"
+            "#,
+        )
+        .unwrap();
+
+        let schedule = config_file.schedule.unwrap();
+
+        assert_eq!(
+            schedule.fallback,
+            Fallback::Forward("127.0.0.1:22".parse().unwrap())
+        );
+        assert_eq!(schedule.on_close, OnClose::Release);
+
+        // 2026-10-16 is a Friday
+        assert!(
+            !schedule.is_active(datetime!(2026-10-16 12:00 UTC)),
+            "Business hours"
+        );
+        assert!(
+            schedule.is_active(datetime!(2026-10-16 19:00 UTC)),
+            "Evening"
+        );
+        assert!(
+            schedule.is_active(datetime!(2026-10-17 12:00 UTC)),
+            "Weekend"
+        );
+    }
+
+    #[test]
+    fn defaults_to_close_and_keep() {
+        let config_file = ConfigFile::parse("schedule = { active = [\"Sat,Sun\"] }").unwrap();
+
+        let schedule = config_file.schedule.unwrap();
+
+        assert_eq!(schedule.fallback, Fallback::Close);
+        assert_eq!(schedule.on_close, OnClose::Keep);
+    }
+
+    #[tokio::test]
+    async fn forwards_to_target() {
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target_addr = target.local_addr().unwrap();
+
+        let tarpit = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tarpit_addr = tarpit.local_addr().unwrap();
+
+        let mut client = TcpStream::connect(tarpit_addr).await.unwrap();
+        let (socket, addr) = tarpit.accept().await.unwrap();
+
+        let cancellation_token = CancellationToken::new();
+
+        let forwarding = tokio::task::spawn(forward(
+            socket,
+            addr,
+            target_addr,
+            cancellation_token.clone(),
+        ));
+
+        let (mut upstream, _) = target.accept().await.unwrap();
+
+        upstream.write_all(b"SSH-2.0-real\r\n").await.unwrap();
+
+        let mut buffer = [0_u8; 14];
+        client.read_exact(&mut buffer).await.unwrap();
+
+        assert_eq!(&buffer, b"SSH-2.0-real\r\n");
+
+        cancellation_token.cancel();
+
+        forwarding.await.unwrap();
+
+        assert_eq!(
+            client.read(&mut buffer).await.unwrap(),
+            0,
+            "Closed on shutdown"
+        );
+    }
+}
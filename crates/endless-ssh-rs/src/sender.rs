@@ -2,15 +2,11 @@ use std::io::ErrorKind;
 
 use tracing::{Level, event};
 
-use crate::line::randline;
-
-pub async fn sendline<T: tokio::io::AsyncWriteExt + std::marker::Unpin + std::fmt::Debug>(
+pub async fn send<T: tokio::io::AsyncWriteExt + std::marker::Unpin + std::fmt::Debug>(
     target: &mut T,
-    max_length: usize,
+    bytes: &[u8],
 ) -> Result<usize, ()> {
-    let bytes = randline(max_length);
-
-    match target.write_all(bytes.as_slice()).await {
+    match target.write_all(bytes).await {
         Ok(()) => {
             event!(
                 Level::TRACE,
@@ -64,7 +60,9 @@ mod tests {
 
     use pretty_assertions::assert_eq;
 
-    use crate::sender::sendline;
+    use crate::line::randline;
+    use crate::line::shape::{Alphabet, LineLength};
+    use crate::sender::send;
 
     #[derive(Debug)]
     struct ErrorWrite {
@@ -131,7 +129,11 @@ mod tests {
 
         tokio::pin!(ok_write);
 
-        let r = sendline(&mut ok_write, 100).await;
+        let r = send(
+            &mut ok_write,
+            &randline(LineLength::default(), &Alphabet::default(), 100),
+        )
+        .await;
 
         assert_eq!(Ok(ok_write.written), r);
     }
@@ -144,7 +146,11 @@ mod tests {
 
         tokio::pin!(error_not_connected);
 
-        let r = sendline(&mut error_not_connected, 100).await;
+        let r = send(
+            &mut error_not_connected,
+            &randline(LineLength::default(), &Alphabet::default(), 100),
+        )
+        .await;
 
         assert_eq!(Err(()), r);
     }
@@ -157,7 +163,11 @@ mod tests {
 
         tokio::pin!(error_would_block);
 
-        let r = sendline(&mut error_would_block, 100).await;
+        let r = send(
+            &mut error_would_block,
+            &randline(LineLength::default(), &Alphabet::default(), 100),
+        )
+        .await;
 
         assert_eq!(Ok(0), r);
     }
@@ -170,7 +180,11 @@ mod tests {
 
         tokio::pin!(error_connection_reset);
 
-        let r = sendline(&mut error_connection_reset, 100).await;
+        let r = send(
+            &mut error_connection_reset,
+            &randline(LineLength::default(), &Alphabet::default(), 100),
+        )
+        .await;
 
         assert_eq!(Err(()), r);
     }
@@ -1,9 +1,13 @@
+use std::sync::PoisonError;
+
 use time::SignedDuration;
 use tokio::sync::mpsc::{self, UnboundedSender};
 use tokio::task::JoinHandle;
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use tracing::{Level, event};
 
+use crate::bandwidth::SharedTokenBucket;
 use crate::signal_handlers;
 
 type StdDuration = std::time::Duration;
@@ -15,29 +19,50 @@ pub enum StatisticsMessage {
     TimeSpent(StdDuration),
     // Connects += 1
     NewClient,
+    RepeatOffender,
+    /// The schedule opened (`true`) or closed (`false`).
+    ScheduleTransition(bool),
+    /// Not trapped because the schedule is closed.
+    FallbackClient,
+    /// A client had to wait for the bandwidth cap.
+    DeferredTick,
     LogTotals,
 }
 
 pub struct Statistics {
+    pub active: bool,
+    pub bandwidth: Option<SharedTokenBucket>,
     pub bytes_sent: usize,
     pub connects: u64,
+    pub deferred_ticks: u64,
+    pub fallback_clients: u64,
     pub lost_clients: u64,
     pub processed_clients: u64,
+    pub repeat_offenders: u64,
+    pub schedule_transitions: u64,
     pub time_spent: SignedDuration,
 }
 
 impl Statistics {
     pub fn new(
         cancellation_token: CancellationToken,
+        active: bool,
+        bandwidth: Option<SharedTokenBucket>,
     ) -> (UnboundedSender<StatisticsMessage>, JoinHandle<Statistics>) {
         let (sender, mut receiver) = mpsc::unbounded_channel::<StatisticsMessage>();
 
         let task = tokio::task::spawn(async move {
             let mut s = Self {
+                active,
+                bandwidth,
                 bytes_sent: 0,
                 connects: 0,
+                deferred_ticks: 0,
+                fallback_clients: 0,
                 lost_clients: 0,
                 processed_clients: 0,
+                repeat_offenders: 0,
+                schedule_transitions: 0,
                 time_spent: SignedDuration::ZERO,
             };
 
@@ -53,6 +78,13 @@ impl Statistics {
                             Some(StatisticsMessage::BytesSent(bytes_sent)) => s.bytes_sent += bytes_sent,
                             Some(StatisticsMessage::TimeSpent(duration)) => s.time_spent += duration,
                             Some(StatisticsMessage::NewClient) => s.connects += 1,
+                            Some(StatisticsMessage::RepeatOffender) => s.repeat_offenders += 1,
+                            Some(StatisticsMessage::ScheduleTransition(active)) => {
+                                s.active = active;
+                                s.schedule_transitions += 1;
+                            },
+                            Some(StatisticsMessage::FallbackClient) => s.fallback_clients += 1,
+                            Some(StatisticsMessage::DeferredTick) => s.deferred_ticks += 1,
                             Some(StatisticsMessage::LogTotals) => s.log_totals(),
                             None => {
                                 // the end
@@ -72,10 +104,21 @@ impl Statistics {
     pub fn log_totals(&self) {
         let time_spent = self.time_spent;
         let bytes_sent = self.bytes_sent;
+        // percentage
+        let bandwidth_utilisation = self.bandwidth.as_ref().map(|bandwidth| {
+            bandwidth
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .utilisation(Instant::now())
+        });
 
         event!(
             Level::INFO,
             connects = self.connects,
+            repeat_offenders = self.repeat_offenders,
+            active = self.active,
+            schedule_transitions = self.schedule_transitions,
+            fallback_clients = self.fallback_clients,
             time_spent = format_args!(
                 "{} week(s), {} day(s), {} hour(s), {} minute(s), {}.{:03} second(s)",
                 time_spent.whole_weeks(),
@@ -86,6 +129,8 @@ impl Statistics {
                 time_spent.subsec_milliseconds()
             ),
             ?bytes_sent,
+            deferred_ticks = self.deferred_ticks,
+            ?bandwidth_utilisation,
             "TOTALS",
         );
     }
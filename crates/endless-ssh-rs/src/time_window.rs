@@ -0,0 +1,221 @@
+use std::str::FromStr;
+
+use color_eyre::eyre;
+use serde::Deserialize;
+use time::{OffsetDateTime, UtcOffset, Weekday};
+
+const ALL_DAYS: u8 = 0b111_1111;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// A weekly recurring window, in UTC, like `Mon-Fri 18:00-08:00`, `Sat,Sun` or `22:00-06:00`.
+///
+/// Days and time range are both optional, leaving one out means 'every day' or 'all day'.
+/// When the range ends before it starts it runs past midnight, and the days are those on which
+/// the window opens.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(try_from = "String")]
+pub struct TimeWindow {
+    /// Bit `n` is set when the window opens on the `n`th day from Monday.
+    days: u8,
+    /// Minutes since midnight.
+    start: u16,
+    /// Minutes since midnight, exclusive.
+    end: u16,
+}
+
+impl TimeWindow {
+    pub fn contains(self, at: OffsetDateTime) -> bool {
+        let at = at.to_offset(UtcOffset::UTC);
+        let minute = u16::from(at.hour()) * 60 + u16::from(at.minute());
+
+        let today = at.weekday();
+
+        if self.start < self.end {
+            self.opens_on(today) && self.start <= minute && minute < self.end
+        } else {
+            // runs past midnight (or all day, when start == end)
+            (self.opens_on(today) && self.start <= minute)
+                || (self.opens_on(today.previous()) && minute < self.end)
+        }
+    }
+
+    fn opens_on(self, weekday: Weekday) -> bool {
+        self.days & (1 << weekday.number_days_from_monday()) != 0
+    }
+}
+
+fn parse_weekday(value: &str) -> Result<Weekday, eyre::Report> {
+    match value.to_ascii_lowercase().as_str() {
+        "mon" => Ok(Weekday::Monday),
+        "tue" => Ok(Weekday::Tuesday),
+        "wed" => Ok(Weekday::Wednesday),
+        "thu" => Ok(Weekday::Thursday),
+        "fri" => Ok(Weekday::Friday),
+        "sat" => Ok(Weekday::Saturday),
+        "sun" => Ok(Weekday::Sunday),
+        _ => Err(eyre::Report::msg(format!("Invalid weekday {:?}", value))),
+    }
+}
+
+/// `Mon`, `Mon-Fri`, `Sat,Sun`, `Mon,Wed-Fri`, ranges wrap around (`Fri-Mon`).
+fn parse_days(value: &str) -> Result<u8, eyre::Report> {
+    let mut days = 0;
+
+    for part in value.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let mut day = parse_weekday(from)?;
+                let to = parse_weekday(to)?;
+
+                days |= 1 << day.number_days_from_monday();
+
+                while day != to {
+                    day = day.next();
+                    days |= 1 << day.number_days_from_monday();
+                }
+            },
+            None => days |= 1 << parse_weekday(part)?.number_days_from_monday(),
+        }
+    }
+
+    Ok(days)
+}
+
+/// `HH:MM`, `24:00` is allowed as end of day.
+fn parse_minutes(value: &str) -> Result<u16, eyre::Report> {
+    let invalid = || eyre::Report::msg(format!("Invalid time {:?}, expected HH:MM", value));
+
+    let (hours, minutes) = value.split_once(':').ok_or_else(invalid)?;
+
+    let hours: u16 = hours.parse().map_err(|_| invalid())?;
+    let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+
+    let total = hours * 60 + minutes;
+
+    if minutes >= 60 || total > MINUTES_PER_DAY {
+        return Err(invalid());
+    }
+
+    Ok(total)
+}
+
+impl FromStr for TimeWindow {
+    type Err = eyre::Report;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut days = None;
+        let mut range = None;
+
+        for part in value.split_whitespace() {
+            if part.starts_with(|c: char| c.is_ascii_digit()) {
+                let (start, end) = part.split_once('-').ok_or_else(|| {
+                    eyre::Report::msg(format!(
+                        "Invalid time range {:?}, expected HH:MM-HH:MM",
+                        part
+                    ))
+                })?;
+
+                let previous = range.replace((parse_minutes(start)?, parse_minutes(end)?));
+
+                if previous.is_some() {
+                    return Err(eyre::Report::msg(format!(
+                        "Multiple time ranges in {:?}",
+                        value
+                    )));
+                }
+            } else {
+                let previous = days.replace(parse_days(part)?);
+
+                if previous.is_some() {
+                    return Err(eyre::Report::msg(format!(
+                        "Multiple day lists in {:?}",
+                        value
+                    )));
+                }
+            }
+        }
+
+        if days.is_none() && range.is_none() {
+            return Err(eyre::Report::msg("Empty time window"));
+        }
+
+        let (start, end) = range.unwrap_or((0, MINUTES_PER_DAY));
+
+        Ok(Self {
+            days: days.unwrap_or(ALL_DAYS),
+            start: start % MINUTES_PER_DAY,
+            end: end % MINUTES_PER_DAY,
+        })
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = eyre::Report;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use crate::time_window::TimeWindow;
+
+    #[test]
+    fn business_hours() {
+        let window: TimeWindow = "Mon-Fri 09:00-17:00".parse().unwrap();
+
+        // 2026-10-16 is a Friday
+        assert!(window.contains(datetime!(2026-10-16 09:00 UTC)), "Start");
+        assert!(!window.contains(datetime!(2026-10-16 17:00 UTC)), "End");
+        assert!(
+            !window.contains(datetime!(2026-10-17 12:00 UTC)),
+            "Saturday"
+        );
+    }
+
+    #[test]
+    fn past_midnight_belongs_to_opening_day() {
+        let window: TimeWindow = "Fri 22:00-06:00".parse().unwrap();
+
+        assert!(window.contains(datetime!(2026-10-16 23:00 UTC)), "Friday");
+        assert!(
+            window.contains(datetime!(2026-10-17 05:59 UTC)),
+            "Saturday morning"
+        );
+        assert!(
+            !window.contains(datetime!(2026-10-16 05:00 UTC)),
+            "Friday morning"
+        );
+    }
+
+    #[test]
+    fn days_only_and_offsets() {
+        let window: TimeWindow = "Sat,Sun".parse().unwrap();
+
+        assert!(window.contains(datetime!(2026-10-18 00:00 UTC)), "Sunday");
+        assert!(
+            window.contains(datetime!(2026-10-16 23:30 -2)),
+            "Saturday in UTC"
+        );
+        assert!(!window.contains(datetime!(2026-10-19 00:00 UTC)), "Monday");
+    }
+
+    #[test]
+    fn wrapping_day_range() {
+        let window: TimeWindow = "Fri-Mon".parse().unwrap();
+
+        assert!(window.contains(datetime!(2026-10-19 12:00 UTC)), "Monday");
+        assert!(!window.contains(datetime!(2026-10-20 12:00 UTC)), "Tuesday");
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        for value in ["", "Funday", "25:00-26:00", "09:00", "Mon Tue"] {
+            #[expect(unused_must_use, reason = "Testing")]
+            value.parse::<TimeWindow>().unwrap_err();
+        }
+    }
+}
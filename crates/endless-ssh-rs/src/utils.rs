@@ -1,4 +1,5 @@
 pub mod env;
+pub mod fs;
 pub mod url;
 
 use color_eyre::eyre;
@@ -0,0 +1,25 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{self, Context as _};
+
+/// Writes `contents` next to `path` and then renames it to `path`,
+/// so that readers never see a partially written file.
+///
+/// # Errors
+/// When either the write or the rename fails.
+pub async fn write_atomically<C>(path: &Path, contents: C) -> Result<(), eyre::Report>
+where
+    C: AsRef<[u8]>,
+{
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let temporary_path = PathBuf::from(temporary_path);
+
+    tokio::fs::write(&temporary_path, contents)
+        .await
+        .wrap_err_with(|| format!("Failed to write {}", temporary_path.display()))?;
+
+    tokio::fs::rename(&temporary_path, path)
+        .await
+        .wrap_err_with(|| format!("Failed to move {}", temporary_path.display()))
+}